@@ -0,0 +1,723 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use zstd::stream::read::Decoder as ZstdDecoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+use crate::interrupt::check_interrupted;
+
+/// Name of the single file an archive-mode push commits to the repository.
+pub(crate) const ARCHIVE_FILE_NAME: &str = "snapshot.tar.zst";
+
+/// First member of every archive; holds the format version so older
+/// releases refuse archives they cannot read.
+const METADATA_ENTRY: &str = ".file-syncer-archive";
+const METADATA_PREFIX: &str = "file-syncer-archive ";
+const FORMAT_VERSION: u32 = 1;
+
+const BLOCK_SIZE: usize = 512;
+/// Largest value an 11-digit octal ustar size or mtime field can hold.
+const MAX_OCTAL_11: u64 = 0o77_777_777_777;
+/// Largest pax or metadata entry read into memory; the sizes come from the
+/// archive itself and cannot be trusted.
+const MAX_HEADER_DATA: u64 = 64 * 1024;
+
+/// A directory (`src_path` is `None`) or file to store in an archive.
+pub(crate) struct ArchiveEntry {
+    pub(crate) rel_path: PathBuf,
+    pub(crate) src_path: Option<PathBuf>,
+    pub(crate) permissions: fs::Permissions,
+    pub(crate) modified: Option<SystemTime>,
+}
+
+/// Counts reported after writing or extracting an archive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ArchiveStats {
+    pub(crate) files: usize,
+    pub(crate) bytes: u64,
+}
+
+/// Writes `entries` as a zstd-compressed ustar archive at `dst`, sorted by
+/// path so an unchanged tree produces an unchanged archive.
+pub(crate) fn write_archive(
+    dst: &Path,
+    entries: &mut [ArchiveEntry],
+    level: i32,
+) -> Result<ArchiveStats> {
+    entries.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+
+    let file = File::create(dst).with_context(|| format!("failed to create {}", dst.display()))?;
+    let mut encoder = ZstdEncoder::new(BufWriter::new(file), level)?;
+
+    let metadata = format!("{METADATA_PREFIX}{FORMAT_VERSION}\n");
+    write_header(
+        &mut encoder,
+        METADATA_ENTRY,
+        b'0',
+        0o644,
+        metadata.len() as u64,
+        0,
+    )?;
+    encoder.write_all(metadata.as_bytes())?;
+    write_padding(&mut encoder, metadata.len() as u64)?;
+
+    let mut stats = ArchiveStats::default();
+    for entry in entries.iter() {
+        check_interrupted()?;
+        let mut name = archive_name(&entry.rel_path)?;
+        let mode = entry_mode(&entry.permissions, entry.src_path.is_none());
+        let mtime = entry
+            .modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |age| age.as_secs().min(MAX_OCTAL_11));
+
+        let Some(src_path) = &entry.src_path else {
+            name.push('/');
+            write_header(&mut encoder, &name, b'5', mode, 0, mtime)?;
+            continue;
+        };
+
+        let mut src = File::open(src_path)
+            .with_context(|| format!("failed to open {}", src_path.display()))?;
+        let size = src.metadata()?.len();
+        write_header(&mut encoder, &name, b'0', mode, size, mtime)?;
+        let copied = io::copy(&mut (&mut src).take(size), &mut encoder)
+            .with_context(|| format!("failed to archive {}", src_path.display()))?;
+        if copied != size {
+            bail!("{} changed size while being archived", src_path.display());
+        }
+        write_padding(&mut encoder, size)?;
+
+        stats.files += 1;
+        stats.bytes += size;
+    }
+
+    encoder.write_all(&[0; BLOCK_SIZE * 2])?;
+    encoder.finish()?.flush()?;
+    Ok(stats)
+}
+
+/// Extracts an archive written by [`write_archive`] into `dst_dir`.
+pub(crate) fn extract_archive(
+    archive: &Path,
+    dst_dir: &Path,
+    max_size: Option<u64>,
+) -> Result<ArchiveStats> {
+    let file =
+        File::open(archive).with_context(|| format!("failed to open {}", archive.display()))?;
+    let mut reader =
+        ZstdDecoder::new(file).with_context(|| format!("failed to read {}", archive.display()))?;
+
+    let mut stats = ArchiveStats::default();
+    let mut dirs = Vec::new();
+    let mut version = None;
+    let mut long_name = None;
+    let mut long_size = None;
+    let mut block = [0u8; BLOCK_SIZE];
+
+    loop {
+        check_interrupted()?;
+        reader
+            .read_exact(&mut block)
+            .context("archive is truncated")?;
+        if block.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        let header = Header::parse(&block)?;
+        let size = long_size.take().unwrap_or(header.size);
+        let name = long_name.take().unwrap_or(header.name);
+
+        match header.typeflag {
+            b'x' => {
+                let records = read_data(&mut reader, size)?;
+                (long_name, long_size) = parse_pax_records(&records)?;
+                continue;
+            }
+            b'g' => {
+                skip_data(&mut reader, size)?;
+                continue;
+            }
+            _ => {}
+        }
+
+        if version.is_none() {
+            if name != METADATA_ENTRY {
+                bail!("{} is not a file-syncer archive", archive.display());
+            }
+            let metadata = read_data(&mut reader, size)?;
+            let found = String::from_utf8_lossy(&metadata)
+                .trim()
+                .strip_prefix(METADATA_PREFIX)
+                .and_then(|version| version.parse::<u32>().ok())
+                .with_context(|| format!("{} has a malformed header", archive.display()))?;
+            if found > FORMAT_VERSION {
+                bail!(
+                    "{} uses archive format {found}, but this build only reads up to {FORMAT_VERSION}",
+                    archive.display()
+                );
+            }
+            version = Some(found);
+            continue;
+        }
+
+        let rel_path = safe_relative_path(&name)?;
+        if crate::has_symlinked_parent(dst_dir, &rel_path) {
+            bail!("archive entry {name:?} would be extracted through a symlink");
+        }
+        let path = dst_dir.join(&rel_path);
+        // An entry replaces a symlink at its own path rather than writing
+        // to wherever the link points.
+        if path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to replace symlink {}", path.display()))?;
+        }
+        match header.typeflag {
+            b'0' | b'\0' => {
+                if let Some(max_size) = max_size
+                    && stats.bytes.saturating_add(size) > max_size
+                {
+                    bail!(
+                        "{} decompresses to more than {max_size} bytes, the --max-decompressed-size limit",
+                        archive.display()
+                    );
+                }
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("failed to create {}", parent.display()))?;
+                }
+                let mut dst = create_file(&path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                let copied = io::copy(&mut (&mut reader).take(size), &mut dst)
+                    .with_context(|| format!("failed to extract {}", path.display()))?;
+                if copied != size {
+                    bail!("archive is truncated");
+                }
+                skip_padding(&mut reader, size)?;
+                if header.mtime > 0 {
+                    dst.set_modified(UNIX_EPOCH + Duration::from_secs(header.mtime))?;
+                }
+                drop(dst);
+                set_mode(&path, header.mode)?;
+
+                stats.files += 1;
+                stats.bytes += size;
+            }
+            b'5' => {
+                fs::create_dir_all(&path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                dirs.push((path, header.mode));
+            }
+            // Links and special files are never written by file-syncer.
+            _ => {
+                skip_data(&mut reader, size)?;
+            }
+        }
+    }
+
+    if version.is_none() {
+        bail!("{} is not a file-syncer archive", archive.display());
+    }
+
+    // Applied last so read-only directories do not block their own contents.
+    for (path, mode) in dirs.iter().rev() {
+        set_mode(path, *mode)?;
+    }
+
+    Ok(stats)
+}
+
+struct Header {
+    name: String,
+    mode: u32,
+    size: u64,
+    mtime: u64,
+    typeflag: u8,
+}
+
+impl Header {
+    fn parse(block: &[u8; BLOCK_SIZE]) -> Result<Self> {
+        let stored = parse_octal(&block[148..156])?;
+        let actual: u64 = block
+            .iter()
+            .enumerate()
+            .map(|(index, &byte)| {
+                if (148..156).contains(&index) {
+                    u64::from(b' ')
+                } else {
+                    u64::from(byte)
+                }
+            })
+            .sum();
+        if stored != actual {
+            bail!("archive header checksum mismatch");
+        }
+
+        let mut name = field_str(&block[0..100]);
+        let prefix = field_str(&block[345..500]);
+        if &block[257..262] == b"ustar" && !prefix.is_empty() {
+            name = format!("{prefix}/{name}");
+        }
+
+        Ok(Header {
+            name,
+            mode: parse_octal(&block[100..108])? as u32,
+            size: parse_octal(&block[124..136])?,
+            mtime: parse_octal(&block[136..148])?,
+            typeflag: block[156],
+        })
+    }
+}
+
+fn write_header(
+    out: &mut impl Write,
+    name: &str,
+    typeflag: u8,
+    mode: u32,
+    size: u64,
+    mtime: u64,
+) -> Result<()> {
+    // Names and sizes that do not fit the ustar fields go in a PAX header.
+    let mut records = Vec::new();
+    if name.len() > 100 {
+        records.extend(pax_record("path", name));
+    }
+    if size > MAX_OCTAL_11 {
+        records.extend(pax_record("size", &size.to_string()));
+    }
+    if !records.is_empty() {
+        write_header(out, "././@PaxHeader", b'x', 0o644, records.len() as u64, 0)?;
+        out.write_all(&records)?;
+        write_padding(out, records.len() as u64)?;
+    }
+
+    let mut block = [0u8; BLOCK_SIZE];
+    let name_bytes = &name.as_bytes()[..name.len().min(100)];
+    block[..name_bytes.len()].copy_from_slice(name_bytes);
+    write_octal(&mut block[100..108], u64::from(mode));
+    write_octal(&mut block[108..116], 0);
+    write_octal(&mut block[116..124], 0);
+    write_octal(&mut block[124..136], size.min(MAX_OCTAL_11));
+    write_octal(&mut block[136..148], mtime);
+    block[156] = typeflag;
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+
+    block[148..156].fill(b' ');
+    let checksum: u64 = block.iter().map(|&byte| u64::from(byte)).sum();
+    write_octal(&mut block[148..155], checksum);
+
+    out.write_all(&block)?;
+    Ok(())
+}
+
+/// Formats one `"<len> <key>=<value>\n"` record, where `len` counts itself.
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let body = format!(" {key}={value}\n");
+    let mut len = body.len() + 1;
+    while (len.to_string().len() + body.len()) != len {
+        len += 1;
+    }
+    format!("{len}{body}").into_bytes()
+}
+
+fn parse_pax_records(records: &[u8]) -> Result<(Option<String>, Option<u64>)> {
+    let mut path = None;
+    let mut size = None;
+    let mut rest = records;
+    while !rest.is_empty() {
+        let malformed = || anyhow::anyhow!("malformed PAX header in archive");
+        let space = rest
+            .iter()
+            .position(|&byte| byte == b' ')
+            .ok_or_else(malformed)?;
+        let len: usize = std::str::from_utf8(&rest[..space])?
+            .parse()
+            .map_err(|_| malformed())?;
+        if len <= space || len > rest.len() {
+            return Err(malformed());
+        }
+        let record = std::str::from_utf8(&rest[space + 1..len - 1])?;
+        match record.split_once('=') {
+            Some(("path", value)) => path = Some(value.to_string()),
+            Some(("size", value)) => size = Some(value.parse().map_err(|_| malformed())?),
+            _ => {}
+        }
+        rest = &rest[len..];
+    }
+    Ok((path, size))
+}
+
+fn read_data(reader: &mut impl Read, size: u64) -> Result<Vec<u8>> {
+    if size > MAX_HEADER_DATA {
+        bail!("archive header entry of {size} bytes is larger than {MAX_HEADER_DATA} bytes");
+    }
+    let mut data = Vec::new();
+    reader.take(size).read_to_end(&mut data)?;
+    if data.len() as u64 != size {
+        bail!("archive is truncated");
+    }
+    skip_padding(reader, size)?;
+    Ok(data)
+}
+
+fn skip_data(reader: &mut impl Read, size: u64) -> Result<()> {
+    if io::copy(&mut reader.take(size), &mut io::sink())? != size {
+        bail!("archive is truncated");
+    }
+    skip_padding(reader, size)?;
+    Ok(())
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE
+}
+
+fn write_padding(out: &mut impl Write, size: u64) -> io::Result<()> {
+    out.write_all(&[0; BLOCK_SIZE][..padding(size)])
+}
+
+fn skip_padding(reader: &mut impl Read, size: u64) -> io::Result<()> {
+    reader.read_exact(&mut [0; BLOCK_SIZE][..padding(size)])
+}
+
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{value:0digits$o}");
+    field[..digits].copy_from_slice(text.as_bytes());
+    field[digits] = 0;
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64> {
+    let text = field_str(field);
+    let text = text.trim_matches(|ch: char| ch == ' ' || ch == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).with_context(|| format!("invalid number in archive: {text:?}"))
+}
+
+fn field_str(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Joins the components of `rel_path` with `/`, as tar expects on every
+/// platform.
+fn archive_name(rel_path: &Path) -> Result<String> {
+    let mut parts = Vec::new();
+    for component in rel_path.components() {
+        let Component::Normal(part) = component else {
+            bail!("cannot archive path {}", rel_path.display());
+        };
+        let Some(part) = part.to_str() else {
+            bail!("cannot archive non-UTF-8 path {}", rel_path.display());
+        };
+        parts.push(part);
+    }
+    Ok(parts.join("/"))
+}
+
+/// Rejects absolute paths and `..` so extraction stays inside the folder.
+/// Creates or truncates `path`, failing rather than following a symlink
+/// that appeared there since it was checked.
+fn create_file(path: &Path) -> io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    options.open(path)
+}
+
+fn safe_relative_path(name: &str) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in name
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+    {
+        if part == ".." || part.contains('\\') || Path::new(part).has_root() {
+            bail!("archive entry escapes the destination folder: {name:?}");
+        }
+        path.push(part);
+    }
+    if path.as_os_str().is_empty() {
+        bail!("archive entry has an empty path");
+    }
+    Ok(path)
+}
+
+#[cfg(unix)]
+fn entry_mode(permissions: &fs::Permissions, _is_dir: bool) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    permissions.mode() & 0o777
+}
+
+#[cfg(not(unix))]
+fn entry_mode(permissions: &fs::Permissions, is_dir: bool) -> u32 {
+    match (is_dir, permissions.readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Setuid, setgid and sticky bits from an archive are never applied.
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+        .with_context(|| format!("failed to set permissions on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    fs::set_permissions(path, permissions)
+        .with_context(|| format!("failed to set permissions on {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries_for(root: &Path) -> Vec<ArchiveEntry> {
+        walkdir::WalkDir::new(root)
+            .min_depth(1)
+            .into_iter()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let metadata = entry.metadata().unwrap();
+                ArchiveEntry {
+                    rel_path: entry.path().strip_prefix(root).unwrap().to_path_buf(),
+                    src_path: (!metadata.is_dir()).then(|| entry.path().to_path_buf()),
+                    permissions: metadata.permissions(),
+                    modified: metadata.modified().ok(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn archive_round_trips_nested_tree() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let long_dir = "nested directory ".repeat(6);
+        let files = [
+            ("top.txt", "top level".to_string()),
+            ("a/b/c/deep.txt", "deep file".to_string()),
+            ("a/empty.bin", String::new()),
+            (&format!("{long_dir}/long name.txt"), "x".repeat(1500)),
+        ];
+        for (path, contents) in &files {
+            let path = source_dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::create_dir_all(source_dir.path().join("empty/dir")).unwrap();
+
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive = archive_dir.path().join(ARCHIVE_FILE_NAME);
+        let written = write_archive(&archive, &mut entries_for(source_dir.path()), 3).unwrap();
+        assert_eq!(written.files, files.len());
+
+        let restored_dir = tempfile::tempdir().unwrap();
+        let extracted = extract_archive(&archive, restored_dir.path(), None).unwrap();
+        assert_eq!(extracted, written);
+
+        for (path, contents) in &files {
+            assert_eq!(
+                &fs::read_to_string(restored_dir.path().join(path)).unwrap(),
+                contents
+            );
+        }
+        assert!(restored_dir.path().join("empty/dir").is_dir());
+        assert!(!restored_dir.path().join(METADATA_ENTRY).exists());
+        assert_eq!(
+            fs::metadata(source_dir.path().join("top.txt"))
+                .unwrap()
+                .modified()
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            fs::metadata(restored_dir.path().join("top.txt"))
+                .unwrap()
+                .modified()
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn archive_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let source_dir = tempfile::tempdir().unwrap();
+        let script = source_dir.path().join("run.sh");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o750)).unwrap();
+
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive = archive_dir.path().join(ARCHIVE_FILE_NAME);
+        write_archive(&archive, &mut entries_for(source_dir.path()), 3).unwrap();
+
+        let restored_dir = tempfile::tempdir().unwrap();
+        extract_archive(&archive, restored_dir.path(), None).unwrap();
+        let mode = fs::metadata(restored_dir.path().join("run.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_drops_setuid_and_does_not_follow_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive = archive_dir.path().join(ARCHIVE_FILE_NAME);
+        let write_raw = |entries: &[(&str, u32)]| {
+            let mut encoder = ZstdEncoder::new(File::create(&archive).unwrap(), 3).unwrap();
+            let metadata = format!("{METADATA_PREFIX}{FORMAT_VERSION}\n");
+            for (name, contents, mode) in [(METADATA_ENTRY, metadata.as_str(), 0o644)]
+                .into_iter()
+                .chain(
+                    entries
+                        .iter()
+                        .map(|(name, mode)| (*name, "archived", *mode)),
+                )
+            {
+                let size = contents.len() as u64;
+                write_header(&mut encoder, name, b'0', mode, size, 0).unwrap();
+                encoder.write_all(contents.as_bytes()).unwrap();
+                write_padding(&mut encoder, size).unwrap();
+            }
+            encoder.write_all(&[0; BLOCK_SIZE * 2]).unwrap();
+            encoder.finish().unwrap();
+        };
+
+        let restored_dir = tempfile::tempdir().unwrap();
+        write_raw(&[("suid", 0o4755)]);
+        extract_archive(&archive, restored_dir.path(), None).unwrap();
+        let mode = fs::metadata(restored_dir.path().join("suid"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o755);
+
+        let outside = tempfile::tempdir().unwrap();
+        let target = outside.path().join("target.txt");
+        fs::write(&target, "outside").unwrap();
+        std::os::unix::fs::symlink(&target, restored_dir.path().join("file.txt")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), restored_dir.path().join("dir")).unwrap();
+
+        write_raw(&[("file.txt", 0o644)]);
+        extract_archive(&archive, restored_dir.path(), None).unwrap();
+        let extracted = restored_dir.path().join("file.txt");
+        assert!(!extracted.symlink_metadata().unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(extracted).unwrap(), "archived");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "outside");
+
+        write_raw(&[("dir/target.txt", 0o644)]);
+        let err = extract_archive(&archive, restored_dir.path(), None).unwrap_err();
+        assert!(err.to_string().contains("through a symlink"), "{err}");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "outside");
+    }
+
+    #[test]
+    fn extract_stops_at_the_size_limit() {
+        let source_dir = tempfile::tempdir().unwrap();
+        fs::write(source_dir.path().join("a.txt"), "a".repeat(600)).unwrap();
+        fs::write(source_dir.path().join("b.txt"), "b".repeat(600)).unwrap();
+
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive = archive_dir.path().join(ARCHIVE_FILE_NAME);
+        write_archive(&archive, &mut entries_for(source_dir.path()), 3).unwrap();
+
+        let restored_dir = tempfile::tempdir().unwrap();
+        let err = extract_archive(&archive, restored_dir.path(), Some(1000)).unwrap_err();
+        assert!(err.to_string().contains("--max-decompressed-size"), "{err}");
+        extract_archive(&archive, restored_dir.path(), Some(1200)).unwrap();
+    }
+
+    #[test]
+    fn extract_rejects_foreign_and_newer_archives() {
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive = archive_dir.path().join(ARCHIVE_FILE_NAME);
+        let restored_dir = tempfile::tempdir().unwrap();
+
+        let write_raw = |name: &str, contents: &str| {
+            let mut encoder = ZstdEncoder::new(File::create(&archive).unwrap(), 3).unwrap();
+            write_header(&mut encoder, name, b'0', 0o644, contents.len() as u64, 0).unwrap();
+            encoder.write_all(contents.as_bytes()).unwrap();
+            write_padding(&mut encoder, contents.len() as u64).unwrap();
+            encoder.write_all(&[0; BLOCK_SIZE * 2]).unwrap();
+            encoder.finish().unwrap();
+        };
+
+        write_raw("other.txt", "not metadata");
+        let err = extract_archive(&archive, restored_dir.path(), None).unwrap_err();
+        assert!(err.to_string().contains("not a file-syncer archive"));
+
+        write_raw(METADATA_ENTRY, "file-syncer-archive 99\n");
+        let err = extract_archive(&archive, restored_dir.path(), None).unwrap_err();
+        assert!(err.to_string().contains("archive format 99"));
+
+        // A forged size must be rejected before anything is buffered.
+        let mut encoder = ZstdEncoder::new(File::create(&archive).unwrap(), 3).unwrap();
+        write_header(&mut encoder, METADATA_ENTRY, b'0', 0o644, MAX_OCTAL_11, 0).unwrap();
+        encoder.finish().unwrap();
+        let err = extract_archive(&archive, restored_dir.path(), None).unwrap_err();
+        assert!(
+            err.to_string().contains("is larger than 65536 bytes"),
+            "{err}"
+        );
+
+        let metadata = format!("{METADATA_PREFIX}{FORMAT_VERSION}\n");
+        let mut encoder = ZstdEncoder::new(File::create(&archive).unwrap(), 3).unwrap();
+        write_header(
+            &mut encoder,
+            METADATA_ENTRY,
+            b'0',
+            0o644,
+            metadata.len() as u64,
+            0,
+        )
+        .unwrap();
+        encoder.write_all(metadata.as_bytes()).unwrap();
+        write_padding(&mut encoder, metadata.len() as u64).unwrap();
+        write_header(&mut encoder, "link", b'2', 0o644, MAX_OCTAL_11, 0).unwrap();
+        encoder.finish().unwrap();
+        let err = extract_archive(&archive, restored_dir.path(), None).unwrap_err();
+        assert!(err.to_string().contains("archive is truncated"), "{err}");
+    }
+
+    #[test]
+    fn safe_relative_path_rejects_escapes() {
+        assert_eq!(
+            safe_relative_path("./a/b.txt").unwrap(),
+            Path::new("a").join("b.txt")
+        );
+        for name in ["../evil", "a/../../evil", "", "a\\..\\evil"] {
+            assert!(safe_relative_path(name).is_err(), "{name:?}");
+        }
+    }
+}
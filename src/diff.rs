@@ -0,0 +1,281 @@
+//! Line-based unified diffs for `--mode verify --diff`.
+
+/// Unchanged lines shown around each change, as in `diff -u`.
+const CONTEXT_LINES: usize = 3;
+/// Beyond this many edits the changed region is shown as one replacement
+/// instead of searching further; the search keeps O(D²) state.
+const MAX_EDIT_DISTANCE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// One line of the edit script, with the old and new line positions it
+/// applies at.
+#[derive(Debug, Clone, Copy)]
+struct Op {
+    kind: OpKind,
+    old: usize,
+    new: usize,
+}
+
+/// Returns a unified diff from `old` to `new` with `--- old_label` and
+/// `+++ new_label` headers, or an empty string when they are equal.
+pub(crate) fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+    if ops.iter().all(|op| op.kind == OpKind::Equal) {
+        return String::new();
+    }
+
+    let mut out = format!("--- {old_label}\n+++ {new_label}\n");
+    let mut start = 0;
+    while let Some(first_change) = ops[start..]
+        .iter()
+        .position(|op| op.kind != OpKind::Equal)
+        .map(|offset| start + offset)
+    {
+        let hunk_start = first_change.saturating_sub(CONTEXT_LINES).max(start);
+        let mut end = first_change;
+        loop {
+            while end < ops.len() && ops[end].kind != OpKind::Equal {
+                end += 1;
+            }
+            let equal_run = ops[end..]
+                .iter()
+                .take_while(|op| op.kind == OpKind::Equal)
+                .count();
+            if end + equal_run == ops.len() || equal_run > 2 * CONTEXT_LINES {
+                end += equal_run.min(CONTEXT_LINES);
+                break;
+            }
+            end += equal_run;
+        }
+
+        write_hunk(&mut out, &ops[hunk_start..end], &old_lines, &new_lines);
+        start = end;
+    }
+    out
+}
+
+fn write_hunk(out: &mut String, ops: &[Op], old_lines: &[&str], new_lines: &[&str]) {
+    let old_len = ops.iter().filter(|op| op.kind != OpKind::Insert).count();
+    let new_len = ops.iter().filter(|op| op.kind != OpKind::Delete).count();
+    // An empty range is addressed by the line before it.
+    let range = |pos: usize, len: usize| {
+        let start = if len == 0 { pos } else { pos + 1 };
+        format!("{start},{len}")
+    };
+    out.push_str(&format!(
+        "@@ -{} +{} @@\n",
+        range(ops[0].old, old_len),
+        range(ops[0].new, new_len)
+    ));
+
+    for op in ops {
+        let (marker, line) = match op.kind {
+            OpKind::Equal => (' ', old_lines[op.old]),
+            OpKind::Delete => ('-', old_lines[op.old]),
+            OpKind::Insert => ('+', new_lines[op.new]),
+        };
+        out.push(marker);
+        out.push_str(line);
+        if !line.ends_with('\n') {
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+/// Builds the edit script turning `old` into `new`. The common prefix and
+/// suffix are matched directly and Myers' algorithm runs on the rest.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut ops: Vec<Op> = (0..prefix)
+        .map(|idx| Op {
+            kind: OpKind::Equal,
+            old: idx,
+            new: idx,
+        })
+        .collect();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    let middle = myers(old_mid, new_mid).unwrap_or_else(|| replace_all(old_mid, new_mid));
+    ops.extend(middle.into_iter().map(|op| Op {
+        kind: op.kind,
+        old: op.old + prefix,
+        new: op.new + prefix,
+    }));
+    ops.extend((0..suffix).map(|idx| Op {
+        kind: OpKind::Equal,
+        old: old.len() - suffix + idx,
+        new: new.len() - suffix + idx,
+    }));
+    ops
+}
+
+fn replace_all(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let deletes = (0..old.len()).map(|idx| Op {
+        kind: OpKind::Delete,
+        old: idx,
+        new: 0,
+    });
+    let inserts = (0..new.len()).map(|idx| Op {
+        kind: OpKind::Insert,
+        old: old.len(),
+        new: idx,
+    });
+    deletes.chain(inserts).collect()
+}
+
+/// Myers' O(ND) shortest edit script, or `None` when it needs more than
+/// [`MAX_EDIT_DISTANCE`] edits.
+fn myers(old: &[&str], new: &[&str]) -> Option<Vec<Op>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (old.len() + new.len()).min(MAX_EDIT_DISTANCE) as isize;
+    // `v[k + offset]` is the furthest x reached on diagonal k = x - y.
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+
+    let mut found = false;
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, row) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        // `row` holds diagonals -d..=d as they were before step d.
+        let at = |k: isize| row[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { at(prev_k) };
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            ops.push(Op {
+                kind: OpKind::Equal,
+                old: x as usize,
+                new: y as usize,
+            });
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                ops.push(Op {
+                    kind: OpKind::Insert,
+                    old: x as usize,
+                    new: y as usize,
+                });
+            } else {
+                x -= 1;
+                ops.push(Op {
+                    kind: OpKind::Delete,
+                    old: x as usize,
+                    new: y as usize,
+                });
+            }
+        }
+    }
+    ops.reverse();
+    Some(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unified_diff_of_equal_text_is_empty() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "a/x", "b/x"), "");
+    }
+
+    #[test]
+    fn unified_diff_produces_hunks_with_context() {
+        let old: String = (1..=20).map(|n| format!("line {n}\n")).collect();
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 18\n", "")
+            + "line 21\n";
+
+        let diff = unified_diff(&old, &new, "a/notes.txt", "b/notes.txt");
+        assert_eq!(
+            diff,
+            "--- a/notes.txt\n+++ b/notes.txt\n\
+             @@ -1,5 +1,5 @@\n line 1\n-line 2\n+line two\n line 3\n line 4\n line 5\n\
+             @@ -15,6 +15,6 @@\n line 15\n line 16\n line 17\n-line 18\n line 19\n line 20\n+line 21\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_handles_empty_sides_and_missing_newline() {
+        assert_eq!(
+            unified_diff("", "new", "a/f", "b/f"),
+            "--- a/f\n+++ b/f\n@@ -0,0 +1,1 @@\n+new\n\\ No newline at end of file\n"
+        );
+        assert_eq!(
+            unified_diff("keep\nold\n", "keep\n", "a/f", "b/f"),
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,1 @@\n keep\n-old\n"
+        );
+    }
+
+    #[test]
+    fn myers_edit_script_reproduces_both_sides() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let ops = myers(&old, &new).unwrap();
+
+        let edits = ops.iter().filter(|op| op.kind != OpKind::Equal).count();
+        assert_eq!(edits, 5);
+        let rebuilt_old: Vec<_> = ops
+            .iter()
+            .filter(|op| op.kind != OpKind::Insert)
+            .map(|op| old[op.old])
+            .collect();
+        let rebuilt_new: Vec<_> = ops
+            .iter()
+            .filter(|op| op.kind != OpKind::Delete)
+            .map(|op| new[op.new])
+            .collect();
+        assert_eq!(rebuilt_old, old);
+        assert_eq!(rebuilt_new, new);
+    }
+}
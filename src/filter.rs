@@ -0,0 +1,31 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Rewrites or drops file contents while they are synced, e.g. to redact
+/// secrets before a push. Filters always see plain contents: before
+/// compression on push and after decompression on pull. They run on the
+/// rayon workers, so implementations must be thread-safe.
+pub trait ContentFilter: Send + Sync {
+    /// Returns the contents to write for `rel`, or `None` to leave the file out.
+    fn transform(&self, rel: &Path, bytes: &[u8]) -> Result<Option<Vec<u8>>>;
+}
+
+/// Runs `filters` in order, each on the previous one's output, and stops as
+/// soon as one drops the file.
+pub(crate) fn apply_filters(
+    filters: &[&dyn ContentFilter],
+    rel: &Path,
+    mut bytes: Vec<u8>,
+) -> Result<Option<Vec<u8>>> {
+    for filter in filters {
+        match filter
+            .transform(rel, &bytes)
+            .with_context(|| format!("content filter failed for {}", rel.display()))?
+        {
+            Some(next) => bytes = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(bytes))
+}
@@ -0,0 +1,256 @@
+//! Push backend that writes files through the GitHub Contents API, for hosts
+//! that have HTTP access but no git binary. Each new or changed file becomes
+//! its own commit on the branch.
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+use serde_json::{Value, json};
+
+/// API root used when no `--api-url` is given.
+pub(crate) const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Most files one API push will handle; every file costs at least one
+/// request, so larger trees should use the git backend.
+pub(crate) const MAX_API_FILES: usize = 200;
+
+/// A file to store at `path` in the repository.
+pub(crate) struct ApiFile {
+    pub(crate) path: String,
+    pub(crate) contents: Vec<u8>,
+}
+
+/// What an API push changed on the branch.
+#[derive(Debug, Default)]
+pub(crate) struct ApiPushOutcome {
+    pub(crate) committed: usize,
+    pub(crate) last_subject: Option<String>,
+    pub(crate) last_commit_sha: Option<String>,
+}
+
+pub(crate) struct GithubClient {
+    http: Client,
+    api_url: String,
+    owner: String,
+    repo: String,
+    token: String,
+}
+
+impl GithubClient {
+    pub(crate) fn new(api_url: &str, token: &str, repo_url: &str) -> Result<Self> {
+        let (owner, repo) = parse_repository(repo_url)?;
+        let http = Client::builder()
+            .user_agent(concat!("file-syncer/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("failed to create HTTP client")?;
+        Ok(Self {
+            http,
+            api_url: api_url.trim_end_matches('/').to_string(),
+            owner,
+            repo,
+            token: token.to_string(),
+        })
+    }
+
+    fn contents_url(&self, path: &str) -> String {
+        format!(
+            "{}/repos/{}/{}/contents/{}",
+            self.api_url,
+            self.owner,
+            self.repo,
+            encode_path(path)
+        )
+    }
+
+    fn send(&self, request: reqwest::blocking::RequestBuilder) -> Result<Response> {
+        Ok(request
+            .bearer_auth(&self.token)
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .send()?)
+    }
+
+    /// Blob sha of `path` on `branch`, or `None` when it does not exist.
+    fn blob_sha(&self, path: &str, branch: &str) -> Result<Option<String>> {
+        let response = self.send(
+            self.http
+                .get(self.contents_url(path))
+                .query(&[("ref", branch)]),
+        )?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = json_body(response)?;
+        if body.is_array() {
+            bail!("{path} is a directory on branch {branch}");
+        }
+        let sha = body["sha"]
+            .as_str()
+            .ok_or_else(|| anyhow!("response for {path} has no sha"))?;
+        Ok(Some(sha.to_string()))
+    }
+
+    /// Creates or replaces `path` on `branch` and returns the new commit sha.
+    fn put_file(
+        &self,
+        path: &str,
+        branch: &str,
+        message: &str,
+        contents: &[u8],
+        sha: Option<&str>,
+    ) -> Result<String> {
+        let mut body = json!({
+            "message": message,
+            "content": BASE64.encode(contents),
+            "branch": branch,
+        });
+        if let Some(sha) = sha {
+            body["sha"] = json!(sha);
+        }
+        let response = self.send(self.http.put(self.contents_url(path)).json(&body))?;
+        let body = json_body(response)?;
+        body["commit"]["sha"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("response for {path} has no commit sha"))
+    }
+}
+
+/// Uploads every file in `files` whose contents differ from `branch`.
+pub(crate) fn push_files(
+    client: &GithubClient,
+    branch: &str,
+    subject_prefix: &str,
+    files: &[ApiFile],
+) -> Result<ApiPushOutcome> {
+    let mut outcome = ApiPushOutcome::default();
+    for file in files {
+        crate::interrupt::check_interrupted()?;
+        let existing = client
+            .blob_sha(&file.path, branch)
+            .with_context(|| format!("failed to look up {}", file.path))?;
+        if existing.as_deref() == Some(git_blob_sha(&file.contents).as_str()) {
+            continue;
+        }
+
+        let subject = format!("{subject_prefix}{}", file.path);
+        log::info!("Committing changes: {}", subject);
+        let commit_sha = client
+            .put_file(
+                &file.path,
+                branch,
+                &subject,
+                &file.contents,
+                existing.as_deref(),
+            )
+            .with_context(|| format!("failed to upload {}", file.path))?;
+        outcome.committed += 1;
+        outcome.last_subject = Some(subject);
+        outcome.last_commit_sha = Some(commit_sha);
+    }
+    Ok(outcome)
+}
+
+/// The sha git gives `contents` as a blob, which is what the API reports
+/// for a stored file, so unchanged files can be skipped without a download.
+pub(crate) fn git_blob_sha(contents: &[u8]) -> String {
+    let mut hasher = openssl::sha::Sha1::new();
+    hasher.update(format!("blob {}\0", contents.len()).as_bytes());
+    hasher.update(contents);
+    hasher
+        .finish()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Reads `owner` and `repo` from an HTTPS or SSH repository URL such as
+/// `https://github.com/owner/repo.git` or `git@github.com:owner/repo.git`.
+pub(crate) fn parse_repository(url: &str) -> Result<(String, String)> {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map(|(_, path)| path),
+        None => url.split_once(':').map(|(_, path)| path),
+    };
+    let parts: Option<Vec<&str>> = path.map(|path| {
+        path.trim_end_matches('/')
+            .trim_end_matches(".git")
+            .split('/')
+            .collect()
+    });
+    match parts.as_deref() {
+        Some([owner, repo]) if !owner.is_empty() && !repo.is_empty() => {
+            Ok((owner.to_string(), repo.to_string()))
+        }
+        _ => bail!("cannot read owner and repository from {url}; expected host/owner/repo"),
+    }
+}
+
+/// Percent-encodes each path segment, keeping the `/` separators.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+fn json_body(response: Response) -> Result<Value> {
+    let status = response.status();
+    let text = response.text()?;
+    if !status.is_success() {
+        let message = serde_json::from_str::<Value>(&text)
+            .ok()
+            .and_then(|body| body["message"].as_str().map(str::to_string))
+            .unwrap_or(text);
+        bail!("GitHub API returned {status}: {message}");
+    }
+    serde_json::from_str(&text).context("GitHub API returned invalid JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_blob_sha_matches_git() {
+        // `printf 'hello\n' | git hash-object --stdin`
+        assert_eq!(
+            git_blob_sha(b"hello\n"),
+            "ce013625030ba8dba906f756967f9e9ca394464a"
+        );
+        assert_eq!(
+            git_blob_sha(b""),
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+    }
+
+    #[test]
+    fn parse_repository_reads_https_and_ssh_urls() {
+        for url in [
+            "https://github.com/owner/repo.git",
+            "https://token@github.example.com/owner/repo/",
+            "git@github.com:owner/repo.git",
+            "ssh://git@github.com/owner/repo",
+        ] {
+            assert_eq!(
+                parse_repository(url).unwrap(),
+                ("owner".to_string(), "repo".to_string()),
+                "{url}"
+            );
+        }
+        for url in ["https://github.com/owner", "/srv/git/repo.git", "repo"] {
+            assert!(parse_repository(url).is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn encode_path_escapes_segments() {
+        assert_eq!(encode_path("dir/a file#1.txt"), "dir/a%20file%231.txt");
+    }
+}
@@ -0,0 +1,370 @@
+//! Streaming single-member gzip (RFC 1952) for `--compression-rule GLOB=gzip`.
+
+use std::io::{self, Read, Write};
+
+use miniz_oxide::deflate::core::{CompressorOxide, create_comp_flags_from_zip_params};
+use miniz_oxide::deflate::stream::deflate;
+use miniz_oxide::inflate::stream::{InflateState, inflate};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const DEFLATE_METHOD: u8 = 8;
+const OS_UNKNOWN: u8 = 255;
+const HEADER_LEN: usize = 10;
+const TRAILER_LEN: usize = 8;
+const BUFFER_LEN: usize = 64 * 1024;
+
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+/// The header carries no name, timestamp or host OS, so identical input
+/// yields identical output.
+pub(crate) struct Encoder<W: Write> {
+    inner: W,
+    compressor: Box<CompressorOxide>,
+    buffer: Vec<u8>,
+    crc: u32,
+    len: u32,
+}
+
+impl<W: Write> Encoder<W> {
+    pub(crate) fn new(mut inner: W, level: u8) -> io::Result<Self> {
+        let extra_flags = match level {
+            9.. => 2,
+            1 => 4,
+            _ => 0,
+        };
+        let mut header = [0u8; HEADER_LEN];
+        header[..2].copy_from_slice(&GZIP_MAGIC);
+        header[2] = DEFLATE_METHOD;
+        header[8] = extra_flags;
+        header[9] = OS_UNKNOWN;
+        inner.write_all(&header)?;
+
+        let flags = create_comp_flags_from_zip_params(level.into(), 0, 0);
+        Ok(Self {
+            inner,
+            compressor: Box::new(CompressorOxide::new(flags)),
+            buffer: vec![0; BUFFER_LEN],
+            crc: 0,
+            len: 0,
+        })
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        loop {
+            let result = deflate(&mut self.compressor, &[], &mut self.buffer, MZFlush::Finish);
+            self.inner.write_all(&self.buffer[..result.bytes_written])?;
+            match result.status {
+                Ok(MZStatus::StreamEnd) => break,
+                Ok(_) => {}
+                Err(err) => return Err(invalid_data(format!("gzip compression failed: {err:?}"))),
+            }
+        }
+        self.inner.write_all(&self.crc.to_le_bytes())?;
+        self.inner.write_all(&self.len.to_le_bytes())?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut input = data;
+        while !input.is_empty() {
+            let result = deflate(&mut self.compressor, input, &mut self.buffer, MZFlush::None);
+            self.inner.write_all(&self.buffer[..result.bytes_written])?;
+            input = &input[result.bytes_consumed..];
+            if let Err(err) = result.status {
+                return Err(invalid_data(format!("gzip compression failed: {err:?}")));
+            }
+        }
+        self.crc = crc32_update(self.crc, data);
+        self.len = self.len.wrapping_add(data.len() as u32);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub(crate) struct Decoder<R: Read> {
+    inner: R,
+    state: Box<InflateState>,
+    input: Vec<u8>,
+    pos: usize,
+    end: usize,
+    eof: bool,
+    header_read: bool,
+    done: bool,
+    crc: u32,
+    len: u32,
+}
+
+impl<R: Read> Decoder<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            state: InflateState::new_boxed(DataFormat::Raw),
+            input: vec![0; BUFFER_LEN],
+            pos: 0,
+            end: 0,
+            eof: false,
+            header_read: false,
+            done: false,
+            crc: 0,
+            len: 0,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        if self.pos == self.end && !self.eof {
+            self.end = self.inner.read(&mut self.input)?;
+            self.pos = 0;
+            self.eof = self.end == 0;
+        }
+        Ok(())
+    }
+
+    fn read_exact_input(&mut self, out: &mut [u8], what: &str) -> io::Result<()> {
+        for byte in out {
+            self.fill()?;
+            if self.pos == self.end {
+                return Err(invalid_data(format!("truncated gzip {what}")));
+            }
+            *byte = self.input[self.pos];
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    fn skip_input(&mut self, mut count: usize) -> io::Result<()> {
+        while count > 0 {
+            self.fill()?;
+            if self.pos == self.end {
+                return Err(invalid_data("truncated gzip header"));
+            }
+            let step = count.min(self.end - self.pos);
+            self.pos += step;
+            count -= step;
+        }
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut header = [0u8; HEADER_LEN];
+        self.read_exact_input(&mut header, "header")
+            .map_err(|_| invalid_data("not a gzip file"))?;
+        if header[..2] != GZIP_MAGIC {
+            return Err(invalid_data("not a gzip file"));
+        }
+        if header[2] != DEFLATE_METHOD {
+            return Err(invalid_data(format!(
+                "unsupported gzip compression method {}",
+                header[2]
+            )));
+        }
+
+        let flags = header[3];
+        if flags & FLAG_EXTRA != 0 {
+            let mut len = [0u8; 2];
+            self.read_exact_input(&mut len, "header")?;
+            self.skip_input(usize::from(u16::from_le_bytes(len)))?;
+        }
+        for flag in [FLAG_NAME, FLAG_COMMENT] {
+            if flags & flag != 0 {
+                let mut byte = [0xffu8];
+                while byte[0] != 0 {
+                    self.read_exact_input(&mut byte, "header")?;
+                }
+            }
+        }
+        if flags & FLAG_HCRC != 0 {
+            self.skip_input(2)?;
+        }
+        self.header_read = true;
+        Ok(())
+    }
+
+    fn check_trailer(&mut self) -> io::Result<()> {
+        let mut trailer = [0u8; TRAILER_LEN];
+        self.read_exact_input(&mut trailer, "trailer")?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc != self.crc || len != self.len {
+            return Err(invalid_data("gzip checksum mismatch"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if !self.header_read {
+            self.read_header()?;
+        }
+        if self.done || out.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            self.fill()?;
+            let result = inflate(
+                &mut self.state,
+                &self.input[self.pos..self.end],
+                out,
+                MZFlush::None,
+            );
+            self.pos += result.bytes_consumed;
+            let written = result.bytes_written;
+            self.crc = crc32_update(self.crc, &out[..written]);
+            self.len = self.len.wrapping_add(written as u32);
+
+            match result.status {
+                Ok(MZStatus::StreamEnd) => {
+                    self.check_trailer()?;
+                    self.done = true;
+                    return Ok(written);
+                }
+                Ok(_) | Err(MZError::Buf) => {
+                    if written > 0 {
+                        return Ok(written);
+                    }
+                    if self.eof {
+                        return Err(invalid_data("truncated gzip data"));
+                    }
+                    if result.bytes_consumed == 0 && self.pos < self.end {
+                        return Err(invalid_data("invalid gzip data"));
+                    }
+                }
+                Err(err) => return Err(invalid_data(format!("invalid gzip data: {err:?}"))),
+            }
+        }
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut idx = 0;
+        while idx < 256 {
+            let mut crc = idx as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    0xEDB8_8320 ^ (crc >> 1)
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[idx] = crc;
+            idx += 1;
+        }
+        table
+    };
+
+    !data.iter().fold(!crc, |crc, byte| {
+        TABLE[usize::from((crc as u8) ^ byte)] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+pub(crate) fn encode(data: &[u8], level: u8) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::new(), level).unwrap();
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[cfg(test)]
+pub(crate) fn decode(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    Decoder::new(data).read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_check_value() {
+        assert_eq!(crc32_update(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            crc32_update(crc32_update(0, b"1234"), b"56789"),
+            0xCBF4_3926
+        );
+        assert_eq!(crc32_update(0, b""), 0);
+    }
+
+    #[test]
+    fn decode_reads_gzip_output() {
+        // `printf 'hello\n' | gzip -n`
+        let gzipped = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+            0xc9, 0xe7, 0x02, 0x00, 0x20, 0x30, 0x3a, 0x36, 0x06, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(decode(&gzipped).unwrap(), b"hello\n");
+
+        let mut corrupt = gzipped;
+        corrupt[18] ^= 1;
+        assert!(decode(&corrupt).is_err());
+        assert!(decode(&gzipped[..gzipped.len() - 1]).is_err());
+        assert!(decode(&gzipped[..14]).is_err());
+        assert!(decode(b"hello").is_err());
+    }
+
+    #[test]
+    fn encode_round_trips_deterministically() {
+        let data = b"line of a log file\n".repeat(500);
+        let encoded = encode(&data, 6);
+        assert!(encoded.len() < data.len() / 10);
+        assert_eq!(encoded, encode(&data, 6));
+        assert_eq!(decode(&encoded).unwrap(), data);
+        assert_eq!(decode(&encode(b"", 9)).unwrap(), b"");
+    }
+
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            let count = self.0.len().min(out.len()).min(3);
+            out[..count].copy_from_slice(&self.0[..count]);
+            self.0 = &self.0[count..];
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn streams_larger_than_the_buffers() {
+        let data: Vec<u8> = (0..3 * BUFFER_LEN as u32)
+            .map(|idx| (idx.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        let mut encoder = Encoder::new(Vec::new(), 6).unwrap();
+        for chunk in data.chunks(1000) {
+            encoder.write_all(chunk).unwrap();
+        }
+        let encoded = encoder.finish().unwrap();
+
+        let mut decoded = Vec::new();
+        Decoder::new(Trickle(&encoded))
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        // A decoder can stop early without inflating the rest.
+        let mut head = Vec::new();
+        Decoder::new(encoded.as_slice())
+            .take(1024)
+            .read_to_end(&mut head)
+            .unwrap();
+        assert_eq!(head, data[..1024]);
+    }
+}
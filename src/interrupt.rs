@@ -0,0 +1,54 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+
+/// Conventional exit status for a process stopped by SIGINT (128 + 2).
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Returned once an interrupt has been observed, so callers can unwind
+/// normally and let temp directories and child processes be cleaned up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+pub(crate) fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+pub(crate) fn check_interrupted() -> Result<()> {
+    if is_interrupted() {
+        return Err(Interrupted.into());
+    }
+    Ok(())
+}
+
+/// Turns SIGINT and SIGTERM into a cancellation flag instead of killing the
+/// process outright. Other platforms keep the default Ctrl-C behaviour.
+#[cfg(unix)]
+pub fn install_interrupt_handler() -> Result<()> {
+    use anyhow::Context;
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the action only stores to an atomic, which is async-signal-safe.
+        unsafe {
+            signal_hook_registry::register(signal, || INTERRUPTED.store(true, Ordering::Relaxed))
+        }
+        .context("failed to install interrupt handler")?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install_interrupt_handler() -> Result<()> {
+    Ok(())
+}
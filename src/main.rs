@@ -1,17 +1,29 @@
+use std::io;
 use std::path::PathBuf;
 use std::process;
 use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{ArgGroup, Parser};
-use file_syncer::{Config, MODE_PULL, MODE_PUSH, Mode, init_logger, init_sentry, run};
+use file_syncer::{
+    BACKEND_GIT, BACKEND_GIT_HTTP, Backend, CompressionFormat, Config, EXIT_INTERRUPTED,
+    EXIT_NO_CHANGES, Interrupted, InvalidConfig, MODE_BENCHMARK_COMPRESSION, MODE_LIST, MODE_PULL,
+    MODE_PUSH, MODE_SELF_TEST, MODE_VERIFY, Mode, NoChanges, ON_LOCAL_NEWER_BACKUP,
+    ON_LOCAL_NEWER_OVERWRITE, ON_LOCAL_NEWER_SKIP, OUTPUT_JSON, OUTPUT_NONE, OUTPUT_TEXT,
+    OnLocalNewer, OutputFormat, REPO_URL_STDIN, exit_code, init_logger, init_sentry,
+    install_interrupt_handler, load_env_file, parse_age, parse_compression_rule,
+    parse_deny_pattern, read_repo_url, resolve_log_level, run,
+};
 use sentry::ClientInitGuard;
 
 #[derive(Parser, Debug)]
 #[command(
     name = "file-syncer",
     about = "Sync a local folder with a git repository using push or pull operations.",
+    after_help = "Exit status: 0 success, 1 unexpected error, 2 invalid arguments or configuration, \
+3 no changes to push (--fail-on-no-changes), 4 git or network failure, 5 file system error, \
+130 interrupted.",
     group(
         ArgGroup::new("compression-level")
             .args(&["compression_fast", "compression_default", "compression_max"])
@@ -19,16 +31,36 @@ use sentry::ClientInitGuard;
     )
 )]
 struct CliArgs {
-    #[arg(long, value_name = "MODE", value_parser = [MODE_PUSH, MODE_PULL])]
+    #[arg(long, value_name = "MODE", value_parser = [MODE_PUSH, MODE_PULL, MODE_LIST, MODE_VERIFY, MODE_SELF_TEST, MODE_BENCHMARK_COMPRESSION])]
     mode: String,
-    #[arg(long, value_name = "PATH", help = "Path to the folder to sync")]
-    folder: String,
-    #[arg(long, value_name = "URL", help = "Git repository URL")]
-    repo: String,
-    #[arg(long, default_value = "main", help = "Git branch to use")]
-    branch: String,
+    #[arg(
+        long,
+        value_name = "PATH",
+        required_if_eq_any = [("mode", MODE_PUSH), ("mode", MODE_PULL), ("mode", MODE_VERIFY), ("mode", MODE_SELF_TEST), ("mode", MODE_BENCHMARK_COMPRESSION)],
+        help = "Path to the folder to sync"
+    )]
+    folder: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        required_if_eq_any = [("mode", MODE_PUSH), ("mode", MODE_PULL), ("mode", MODE_LIST), ("mode", MODE_VERIFY)],
+        help = "Git repository URL (\"-\" reads it from stdin to keep tokens out of ps)"
+    )]
+    repo: Option<String>,
+    #[arg(
+        long,
+        default_value = "main",
+        help = "Git branch to use (\"current\" uses the branch checked out in the folder); repeat to push to several branches"
+    )]
+    branch: Vec<String>,
     #[arg(long, value_name = "PATH", help = "SSH private key for git operations")]
     ssh_key: Option<String>,
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "SSH port for ssh:// and user@host:path remotes"
+    )]
+    ssh_port: Option<u16>,
     #[arg(
         long,
         default_value_t = false,
@@ -51,6 +83,13 @@ struct CliArgs {
     compression_max: bool,
     #[arg(long, value_name = "N", value_parser = clap::value_parser!(usize), help = "Set number of rayon worker threads")]
     threads: Option<usize>,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(usize),
+        help = "Set number of threads used for compression [default: --threads]; also sets zstd workers for files of 4 MiB or more [default: 0]"
+    )]
+    compression_threads: Option<usize>,
     #[arg(
         long,
         env = "SENTRY_DSN",
@@ -58,6 +97,530 @@ struct CliArgs {
         help = "Sentry DSN for error reporting"
     )]
     sentry_dsn: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        visible_alias = "prefix-strip",
+        help = "Repository subdirectory to sync the folder into; pull strips it from pulled paths"
+    )]
+    repo_prefix: Option<PathBuf>,
+    #[arg(
+        long,
+        short,
+        default_value_t = false,
+        conflicts_with = "verbose",
+        help = "Only log warnings and errors"
+    )]
+    quiet: bool,
+    #[arg(
+        long,
+        short,
+        action = clap::ArgAction::Count,
+        help = "Increase log verbosity (-v for debug, -vv for trace)"
+    )]
+    verbose: u8,
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        value_parser = ["off", "error", "warn", "info", "debug", "trace"],
+        help = "Set the log level, overriding --quiet, --verbose and RUST_LOG"
+    )]
+    log_level: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "known_hosts file to verify SSH host keys against; unknown hosts are rejected"
+    )]
+    known_hosts: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Reject SSH hosts whose key is not already known"
+    )]
+    strict_host_key_checking: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Skip hidden files and directories (names starting with '.')"
+    )]
+    skip_hidden: bool,
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        help = "Shell command run in the clone before committing; a non-zero exit aborts the push"
+    )]
+    pre_push_cmd: Option<String>,
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        help = "Shell command run in the folder after a successful pull"
+    )]
+    post_pull_cmd: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Trained zstd dictionary used to compress and decompress files"
+    )]
+    zstd_dict: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Create and push an annotated tag after each sync ({date}, {sha} and {branch} are replaced)"
+    )]
+    tag: Option<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        visible_alias = "move-tag",
+        requires = "tag",
+        help = "Replace the tag if it already exists, moving it to the new commit"
+    )]
+    force_tag: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Also skip .svn and .hg metadata directories"
+    )]
+    extra_vcs_skip: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Unix socket to stream newline-delimited JSON progress events to"
+    )]
+    status_socket: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Amend the previous sync commit instead of adding a new one"
+    )]
+    amend: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "On pull, skip local files that already match the repository"
+    )]
+    resume: bool,
+    #[arg(
+        long = "remote",
+        value_name = "NAME",
+        help = "Name of the git remote to clone and push with [default: origin]"
+    )]
+    remote_name: Option<String>,
+    #[arg(
+        long,
+        visible_alias = "exclude-larger-than-repo-quota",
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u64),
+        help = "Stop adding files once their total size would exceed this many bytes"
+    )]
+    total_size_budget: Option<u64>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Use the branch checked out in the folder (or current directory)"
+    )]
+    branch_from_cwd: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print --mode list and --mode verify output as JSON"
+    )]
+    json: bool,
+    #[arg(
+        long = "repo-path",
+        value_name = "PATH",
+        help = "On pull, only check out and sync this path under the prefix (repeatable)"
+    )]
+    repo_paths: Vec<PathBuf>,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(usize),
+        help = "List at most N files per commit message section [default: 200]"
+    )]
+    max_commit_body_lines: Option<usize>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Fail instead of fetching full history when the push clone is shallow"
+    )]
+    no_auto_unshallow: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Record and restore file owner and group (Unix only, needs root)"
+    )]
+    preserve_ownership: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Record and restore extended attributes (Linux only)"
+    )]
+    preserve_xattrs: bool,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = OUTPUT_TEXT,
+        value_parser = [OUTPUT_TEXT, OUTPUT_JSON, OUTPUT_NONE],
+        help = "Print a final push or pull summary as text, json or none"
+    )]
+    output_format: String,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Refuse to push into a repository that has no commits yet"
+    )]
+    require_history: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(usize),
+        help = "Only sync files up to N directory levels deep (1 = top level only)"
+    )]
+    max_depth: Option<usize>,
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "amend",
+        help = "Create one commit per changed file instead of a single sync commit"
+    )]
+    commit_per_file: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "With --mode verify, report drift without exiting non-zero"
+    )]
+    ignore_drift: bool,
+    #[arg(
+        long = "diff",
+        default_value_t = false,
+        help = "With --mode verify, print a unified diff for each differing file"
+    )]
+    show_diff: bool,
+    #[arg(
+        long,
+        value_name = "AGE",
+        value_parser = parse_age,
+        help = "Only push files modified within this age, e.g. 30m, 12h or 7d"
+    )]
+    modified_within: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Directory to create the temporary clone in [default: system temp dir]"
+    )]
+    temp_dir: Option<PathBuf>,
+    #[arg(
+        long = "no-verify",
+        default_value_t = false,
+        help = "Skip git hooks in the clone when committing and pushing"
+    )]
+    skip_git_hooks: bool,
+    #[arg(
+        long = "archive",
+        default_value_t = false,
+        conflicts_with_all = ["zstd_dict", "repo_paths", "preserve_ownership", "preserve_xattrs", "modified_within", "total_size_budget"],
+        help = "Push the folder as a single snapshot.tar.zst and extract it on pull"
+    )]
+    archive_mode: bool,
+    #[arg(
+        long,
+        value_name = "DATE",
+        help = "Author and committer date for sync commits (RFC 3339 or \"now\")"
+    )]
+    commit_date: Option<String>,
+    #[arg(
+        long = "include-git-submodules",
+        default_value_t = false,
+        help = "On pull, initialize submodules recursively and sync their files"
+    )]
+    init_submodules: bool,
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        help = "Suffix for compressed file names, e.g. .zst [default: -zstd]"
+    )]
+    compressed_suffix: Option<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Fetch all branches when cloning instead of only the synced branch"
+    )]
+    all_branches: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Load KEY=VALUE environment variables from a dotenv file"
+    )]
+    env_file: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "env_file",
+        default_value_t = false,
+        help = "Let --env-file values replace variables that are already set"
+    )]
+    env_file_override: bool,
+    #[arg(
+        long = "ssh-option",
+        value_name = "OPTION",
+        help = "Extra ssh -o option, e.g. ProxyJump=bastion (repeatable)"
+    )]
+    ssh_extra_options: Vec<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(usize),
+        help = "Ask before pushing more than this many files"
+    )]
+    warn_file_count: Option<usize>,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u64),
+        help = "Ask before pushing more than this many bytes"
+    )]
+    warn_total_size: Option<u64>,
+    #[arg(
+        long = "yes",
+        short = 'y',
+        default_value_t = false,
+        help = "Confirm large pushes without prompting"
+    )]
+    assume_yes: bool,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        requires = "compress",
+        value_parser = clap::value_parser!(u64),
+        help = "With --compress, store files smaller than this many bytes uncompressed"
+    )]
+    compress_min_size: Option<u64>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "When pushing to several branches, keep going after one fails"
+    )]
+    continue_on_error: bool,
+    #[arg(
+        long = "checkout-orphan",
+        default_value_t = false,
+        conflicts_with = "amend",
+        help = "Push each sync as a single parentless commit, replacing the branch history"
+    )]
+    orphan_branch: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Show git's own progress output for clone, fetch and push"
+    )]
+    verbose_git: bool,
+    #[arg(
+        long = "commit-trailer",
+        value_name = "TRAILER",
+        help = "Add a \"Key: value\" trailer such as Change-Id to sync commits (repeatable)"
+    )]
+    commit_trailers: Vec<String>,
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        value_parser = parse_age,
+        help = "Keep running and sync again after each interval, e.g. 5m or 1h"
+    )]
+    interval: Option<Duration>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "With --interval or --watch, exit on the first failed sync instead of retrying next cycle"
+    )]
+    exit_on_error: bool,
+    #[arg(
+        long,
+        visible_alias = "inotify",
+        default_value_t = false,
+        conflicts_with = "interval",
+        help = "Keep running and push whenever files under the folder change (Linux only)"
+    )]
+    watch: bool,
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_age,
+        requires = "watch",
+        help = "With --watch, wait until changes settle for this long before pushing [default: 2s]"
+    )]
+    debounce: Option<Duration>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Exit with status 3 when a push finds nothing to commit"
+    )]
+    fail_on_no_changes: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32),
+        help = "Clone only the last N commits for a push; deepen and retry once if the push is rejected"
+    )]
+    clone_depth: Option<u32>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Append a JSON line describing each push or pull to this file"
+    )]
+    audit_log: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "REFSPEC",
+        help = "Push to this refspec, e.g. HEAD:refs/heads/snapshots/latest, instead of --branch"
+    )]
+    push_refspec: Option<String>,
+    #[arg(
+        long = "allow-empty",
+        default_value_t = false,
+        help = "Commit and push a heartbeat commit even when nothing changed"
+    )]
+    allow_empty_commit: bool,
+    #[arg(
+        long = "resolve-conflicts",
+        visible_alias = "on-local-newer",
+        value_name = "STRATEGY",
+        default_value = ON_LOCAL_NEWER_OVERWRITE,
+        value_parser = [ON_LOCAL_NEWER_OVERWRITE, ON_LOCAL_NEWER_SKIP, ON_LOCAL_NEWER_BACKUP],
+        help = "On pull, overwrite, skip or back up local files edited after the pulled commit"
+    )]
+    on_local_newer: String,
+    #[arg(
+        long,
+        value_name = "BACKEND",
+        default_value = BACKEND_GIT,
+        value_parser = [BACKEND_GIT, BACKEND_GIT_HTTP],
+        help = "Push with the git binary, or through the GitHub Contents API without one (git-http)"
+    )]
+    backend: String,
+    #[arg(
+        long,
+        env = "GITHUB_TOKEN",
+        hide_env_values = true,
+        value_name = "TOKEN",
+        help = "API token for --backend git-http"
+    )]
+    api_token: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "API root for --backend git-http, e.g. https://github.example.com/api/v3 [default: https://api.github.com]"
+    )]
+    api_url: Option<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Before the summary, list every file a push or pull left out and why"
+    )]
+    list_skipped: bool,
+    #[arg(
+        long = "compression-rule",
+        value_name = "GLOB=FORMAT",
+        value_parser = parse_compression_rule,
+        help = "With --compress, store files matching GLOB as zstd, gzip or none; the first matching rule wins (repeatable, pass the same rules on pull)"
+    )]
+    compression_rules: Vec<(String, CompressionFormat)>,
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "ssh_key",
+        help = "Authenticate HTTPS remotes from ~/.netrc only, without credential helpers or prompts"
+    )]
+    use_netrc: bool,
+    #[arg(
+        long = "check",
+        default_value_t = false,
+        help = "Check the folder, repository access and branch, then exit without syncing"
+    )]
+    check_only: bool,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u64),
+        help = "On pull, fail instead of writing any file that decompresses to more than this many bytes"
+    )]
+    max_decompressed_size: Option<u64>,
+    #[arg(
+        long = "write-latest",
+        default_value_t = false,
+        help = "After each push that commits, also commit a LATEST file naming the sync commit and its date"
+    )]
+    write_latest_pointer: bool,
+    #[arg(
+        long = "deny-content",
+        value_name = "NAME=REGEX",
+        value_parser = parse_deny_pattern,
+        help = "On push, leave out text files whose contents match REGEX, warning with NAME; with --strict, fail the push instead (repeatable)"
+    )]
+    content_deny_patterns: Vec<(String, String)>,
+    #[arg(
+        long = "provenance-trailers",
+        default_value_t = false,
+        help = "Add Synced-By (file-syncer version) and Source-Host trailers to sync commits"
+    )]
+    add_provenance_trailers: bool,
+    #[arg(
+        long = "lfs",
+        default_value_t = false,
+        help = "Store large files through git LFS on push and download LFS objects on pull; needs git-lfs"
+    )]
+    use_lfs: bool,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u64),
+        help = "With --lfs, push new or changed files of at least this many bytes through LFS [default: 52428800]"
+    )]
+    lfs_min_size: Option<u64>,
+    #[arg(
+        long = "lfs-track",
+        value_name = "PATTERN",
+        help = "With --lfs, always store files matching this .gitattributes pattern, such as '*.iso', through LFS (repeatable)"
+    )]
+    lfs_track: Vec<String>,
+    #[arg(
+        long = "repo-readonly",
+        env = "FILE_SYNCER_READONLY",
+        value_parser = clap::builder::BoolishValueParser::new(),
+        default_value_t = false,
+        help = "Refuse to run in push mode, for machines that should only pull"
+    )]
+    read_only: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Check the cloned repository with git fsck --full before syncing and fail on corruption"
+    )]
+    verify_repo: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "On push, try to sync FIFOs, sockets and device files instead of skipping them; a FIFO blocks until written to"
+    )]
+    include_special_files: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "preserve_xattrs",
+        help = "With --preserve-xattrs, also sync security.*, trusted.* and other non-user.* attributes"
+    )]
+    xattrs_all_namespaces: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Fail the push instead of warning when a new path would not check out on Windows or macOS or a file matches --deny-content"
+    )]
+    strict: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print the effective configuration as JSON, with secrets masked, and exit without syncing"
+    )]
+    print_config: bool,
 }
 
 impl TryFrom<CliArgs> for Config {
@@ -72,11 +635,17 @@ impl TryFrom<CliArgs> for Config {
             file_syncer::CompressionLevel::Default
         };
 
+        let mut branches = args.branch.into_iter();
+        let branch = branches.next().unwrap_or_else(|| "main".to_string());
+
         Ok(Config {
             mode: Mode::from_str(&args.mode)?,
-            folder_path: PathBuf::from(args.folder),
-            repo_url: args.repo,
-            branch: args.branch,
+            folder_path: args.folder.map(PathBuf::from).unwrap_or_default(),
+            repo_url: match args.repo {
+                Some(repo) if repo == REPO_URL_STDIN => read_repo_url(io::stdin().lock())?,
+                repo => repo.unwrap_or_default(),
+            },
+            branch,
             ssh_key_path: args.ssh_key,
             compress: args.compress
                 || args.compression_fast
@@ -84,7 +653,84 @@ impl TryFrom<CliArgs> for Config {
                 || args.compression_max,
             compression_level: level,
             thread_count: args.threads,
+            compression_threads: args.compression_threads,
             sentry_dsn: args.sentry_dsn,
+            repo_prefix: args.repo_prefix,
+            known_hosts: args.known_hosts,
+            strict_host_key_checking: args.strict_host_key_checking,
+            skip_hidden: args.skip_hidden,
+            pre_push_cmd: args.pre_push_cmd,
+            post_pull_cmd: args.post_pull_cmd,
+            zstd_dict: args.zstd_dict,
+            tag: args.tag,
+            force_tag: args.force_tag,
+            extra_vcs_skip: args.extra_vcs_skip,
+            status_socket: args.status_socket,
+            amend: args.amend,
+            resume: args.resume,
+            remote_name: args.remote_name,
+            total_size_budget: args.total_size_budget,
+            branch_from_cwd: args.branch_from_cwd,
+            json: args.json,
+            repo_paths: args.repo_paths,
+            max_commit_body_lines: args.max_commit_body_lines,
+            no_auto_unshallow: args.no_auto_unshallow,
+            preserve_ownership: args.preserve_ownership,
+            require_history: args.require_history,
+            max_depth: args.max_depth,
+            commit_per_file: args.commit_per_file,
+            ssh_port: args.ssh_port,
+            ignore_drift: args.ignore_drift,
+            modified_within: args.modified_within,
+            temp_dir: args.temp_dir,
+            skip_git_hooks: args.skip_git_hooks,
+            archive_mode: args.archive_mode,
+            commit_date: args.commit_date,
+            init_submodules: args.init_submodules,
+            compressed_suffix: args.compressed_suffix,
+            all_branches: args.all_branches,
+            show_diff: args.show_diff,
+            ssh_extra_options: args.ssh_extra_options,
+            warn_file_count: args.warn_file_count,
+            warn_total_size: args.warn_total_size,
+            assume_yes: args.assume_yes,
+            preserve_xattrs: args.preserve_xattrs,
+            output_format: OutputFormat::from_str(&args.output_format)?,
+            compress_min_size: args.compress_min_size,
+            extra_branches: branches.collect(),
+            continue_on_error: args.continue_on_error,
+            orphan_branch: args.orphan_branch,
+            verbose_git: args.verbose_git,
+            commit_trailers: args.commit_trailers,
+            interval: args.interval,
+            exit_on_error: args.exit_on_error,
+            watch: args.watch,
+            debounce: args.debounce,
+            fail_on_no_changes: args.fail_on_no_changes,
+            clone_depth: args.clone_depth,
+            audit_log: args.audit_log,
+            push_refspec: args.push_refspec,
+            allow_empty_commit: args.allow_empty_commit,
+            on_local_newer: OnLocalNewer::from_str(&args.on_local_newer)?,
+            backend: Backend::from_str(&args.backend)?,
+            api_token: args.api_token,
+            api_url: args.api_url,
+            list_skipped: args.list_skipped,
+            compression_rules: args.compression_rules,
+            use_netrc: args.use_netrc,
+            check_only: args.check_only,
+            max_decompressed_size: args.max_decompressed_size,
+            write_latest_pointer: args.write_latest_pointer,
+            content_deny_patterns: args.content_deny_patterns,
+            add_provenance_trailers: args.add_provenance_trailers,
+            use_lfs: args.use_lfs,
+            lfs_min_size: args.lfs_min_size,
+            lfs_track: args.lfs_track,
+            read_only: args.read_only,
+            verify_repo: args.verify_repo,
+            include_special_files: args.include_special_files,
+            xattrs_all_namespaces: args.xattrs_all_namespaces,
+            strict: args.strict,
         })
     }
 }
@@ -93,20 +739,52 @@ fn main() {
     let mut sentry_guard: Option<ClientInitGuard> = None;
 
     let result = (|| -> Result<()> {
-        init_logger()?;
-        let args = CliArgs::parse();
-        let config = Config::try_from(args)?;
+        let mut args = CliArgs::parse();
+        if let Some(path) = &args.env_file {
+            // Safety: nothing has started a thread yet; the logger, rayon and
+            // sentry are all set up below.
+            unsafe { load_env_file(path, args.env_file_override) }.context(InvalidConfig)?;
+            // Parse again so arguments backed by `env` see the loaded values.
+            args = CliArgs::parse();
+        }
+        init_logger(
+            resolve_log_level(args.quiet, args.verbose, args.log_level.as_deref())
+                .context(InvalidConfig)?,
+        )?;
+        let print_config = args.print_config;
+        let config = Config::try_from(args).context(InvalidConfig)?;
+        if print_config {
+            println!("{:#}", config.to_json());
+            return Ok(());
+        }
         sentry_guard = init_sentry(config.sentry_dsn.as_deref())?;
+        install_interrupt_handler()?;
         run(&config)
     })();
 
+    if let Err(err) = &result
+        && err.downcast_ref::<Interrupted>().is_some()
+    {
+        drop(sentry_guard);
+        eprintln!("Interrupted; temporary files were cleaned up");
+        process::exit(EXIT_INTERRUPTED);
+    }
+
+    if let Err(err) = &result
+        && err.downcast_ref::<NoChanges>().is_some()
+    {
+        drop(sentry_guard);
+        eprintln!("No changes to push");
+        process::exit(EXIT_NO_CHANGES);
+    }
+
     if let Err(err) = &result {
         sentry::capture_message(&format!("{err:?}"), sentry::Level::Error);
         if let Some(guard) = sentry_guard.take() {
             guard.close(Some(Duration::from_secs(2)));
         }
         eprintln!("Error: {err:?}");
-        process::exit(1);
+        process::exit(exit_code(err));
     }
 
     if let Some(guard) = sentry_guard {
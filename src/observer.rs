@@ -0,0 +1,340 @@
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Operation that produced the report: `push`, `pull`, `copy`, `compress` or `decompress`.
+    pub mode: &'static str,
+    /// Branch that was pushed or pulled.
+    pub branch: Option<String>,
+    pub files: usize,
+    pub bytes: u64,
+    pub commit: Option<String>,
+    /// Full sha of the synced commit: the pushed tip, or the pulled one.
+    pub commit_sha: Option<String>,
+    /// Whether a push sent a new commit to the remote.
+    pub pushed: bool,
+    /// Files left out because the total size budget was exhausted.
+    pub over_budget: Vec<String>,
+    /// Every path the sync left out, with the reason.
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// A file or directory a sync left out. A skipped directory stands for
+/// everything below it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Hidden, with `skip_hidden` set.
+    Hidden,
+    /// Excluded by `.syncinclude` or `.syncignore`.
+    Ignored,
+    /// A directory at the depth limit, whose contents are not synced.
+    MaxDepth,
+    /// Not modified within the configured window.
+    TooOld,
+    /// Past the total size budget.
+    OverBudget,
+    /// Dropped by a content filter.
+    Filtered,
+    /// Contents matched a deny pattern.
+    DeniedContent,
+    /// A FIFO, socket or device file.
+    SpecialFile,
+}
+
+impl SkipReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::Hidden => "hidden",
+            SkipReason::Ignored => "ignored",
+            SkipReason::MaxDepth => "max-depth",
+            SkipReason::TooOld => "too-old",
+            SkipReason::OverBudget => "over-budget",
+            SkipReason::Filtered => "filtered",
+            SkipReason::DeniedContent => "denied-content",
+            SkipReason::SpecialFile => "special-file",
+        }
+    }
+}
+
+impl SyncReport {
+    /// The report as a single JSON object with a fixed set of keys.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "mode": self.mode,
+            "branch": self.branch,
+            "files": self.files,
+            "bytes": self.bytes,
+            "commit": self.commit,
+            "commit_sha": self.commit_sha,
+            "pushed": self.pushed,
+            "over_budget": self.over_budget,
+            "skipped": self
+                .skipped
+                .iter()
+                .map(|skipped| json!({ "path": skipped.path, "reason": skipped.reason.as_str() }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Receives progress callbacks while a sync runs. File events arrive from the
+/// rayon workers, so implementations must be thread-safe.
+pub(crate) trait SyncObserver: Sync {
+    fn on_file(&self, _path: &Path, _bytes: u64) {}
+
+    fn on_commit(&self, _subject: &str) {}
+
+    fn on_done(&self, _report: &SyncReport) {}
+}
+
+#[cfg(unix)]
+pub(crate) use socket::StatusSocket;
+
+/// Opens the status socket when one is configured. Failures only log a warning
+/// so status reporting can never break a sync.
+#[cfg(unix)]
+pub(crate) fn open_status_observer(path: Option<&Path>) -> Option<Box<dyn SyncObserver>> {
+    let socket = StatusSocket::open(path?)?;
+    Some(Box::new(socket))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn open_status_observer(path: Option<&Path>) -> Option<Box<dyn SyncObserver>> {
+    if let Some(path) = path {
+        log::warn!(
+            "Status socket {} ignored: Unix domain sockets are not supported on this platform",
+            path.display()
+        );
+    }
+    None
+}
+
+#[cfg(unix)]
+mod socket {
+    use std::fs;
+    use std::io::{self, Write};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use log::warn;
+    use serde_json::{Value, json};
+
+    use super::{SyncObserver, SyncReport};
+
+    /// How long an event may wait for a client to read. Events are emitted
+    /// from the sync itself, so a client that stops reading is dropped
+    /// rather than allowed to stall it.
+    const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(250);
+
+    /// Streams newline-delimited JSON events to every client connected to a
+    /// Unix domain socket. Pending connections are accepted lazily whenever an
+    /// event is emitted, so no background thread is needed.
+    pub(crate) struct StatusSocket {
+        path: PathBuf,
+        listener: UnixListener,
+        clients: Mutex<Vec<UnixStream>>,
+    }
+
+    impl StatusSocket {
+        /// Binds `path`, replacing a socket left behind by an earlier run.
+        /// Anything else already at `path` is left alone and fails the bind.
+        pub(crate) fn bind(path: &Path) -> io::Result<Self> {
+            match fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        "path exists and is not a socket",
+                    ));
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            let listener = UnixListener::bind(path)?;
+            listener.set_nonblocking(true)?;
+            Ok(StatusSocket {
+                path: path.to_path_buf(),
+                listener,
+                clients: Mutex::new(Vec::new()),
+            })
+        }
+
+        pub(crate) fn open(path: &Path) -> Option<Self> {
+            match Self::bind(path) {
+                Ok(socket) => Some(socket),
+                Err(err) => {
+                    warn!(
+                        "Status socket {} unavailable, continuing without it: {}",
+                        path.display(),
+                        err
+                    );
+                    None
+                }
+            }
+        }
+
+        fn emit(&self, event: Value) {
+            let Ok(mut clients) = self.clients.lock() else {
+                return;
+            };
+
+            while let Ok((stream, _)) = self.listener.accept() {
+                if stream.set_nonblocking(false).is_ok()
+                    && stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT)).is_ok()
+                {
+                    clients.push(stream);
+                }
+            }
+
+            let mut line = event.to_string();
+            line.push('\n');
+            clients.retain_mut(|client| match client.write_all(line.as_bytes()) {
+                Ok(()) => true,
+                Err(err) => {
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) {
+                        warn!("Dropping status socket client that stopped reading");
+                    }
+                    false
+                }
+            });
+        }
+    }
+
+    impl SyncObserver for StatusSocket {
+        fn on_file(&self, path: &Path, bytes: u64) {
+            self.emit(json!({
+                "event": "file",
+                "path": path.to_string_lossy(),
+                "bytes": bytes,
+            }));
+        }
+
+        fn on_commit(&self, subject: &str) {
+            self.emit(json!({ "event": "commit", "subject": subject }));
+        }
+
+        fn on_done(&self, report: &SyncReport) {
+            self.emit(json!({ "event": "done", "report": report.to_json() }));
+        }
+    }
+
+    impl Drop for StatusSocket {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn status_socket_streams_events_to_connected_reader() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("status.sock");
+        let socket = StatusSocket::bind(&socket_path).unwrap();
+
+        let reader = UnixStream::connect(&socket_path).unwrap();
+        socket.on_file(Path::new("dir/file.txt"), 42);
+        socket.on_commit("Sync 1 file (1 added)");
+        socket.on_done(&SyncReport {
+            mode: "push",
+            files: 1,
+            bytes: 42,
+            commit: Some("Sync 1 file (1 added)".to_string()),
+            ..Default::default()
+        });
+        drop(socket);
+
+        let events: Vec<Value> = BufReader::new(reader)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            json!({"event": "file", "path": "dir/file.txt", "bytes": 42})
+        );
+        assert_eq!(
+            events[1],
+            json!({"event": "commit", "subject": "Sync 1 file (1 added)"})
+        );
+        assert_eq!(events[2]["event"], "done");
+        assert_eq!(events[2]["report"]["files"], 1);
+        assert!(!socket_path.exists());
+    }
+
+    #[test]
+    fn status_socket_open_is_noop_when_unavailable() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("missing/dir/status.sock");
+        assert!(StatusSocket::open(&socket_path).is_none());
+    }
+
+    #[test]
+    fn status_socket_replaces_stale_sockets_but_not_other_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("status.sock");
+        let stale = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        drop(stale);
+        drop(StatusSocket::bind(&socket_path).unwrap());
+
+        let file_path = temp_dir.path().join("important.txt");
+        std::fs::write(&file_path, "keep me").unwrap();
+        let link_path = temp_dir.path().join("link.sock");
+        std::os::unix::fs::symlink(&file_path, &link_path).unwrap();
+        for path in [&file_path, &link_path] {
+            let err = StatusSocket::bind(path).err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        }
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "keep me");
+        assert!(link_path.symlink_metadata().is_ok());
+    }
+
+    #[test]
+    fn status_socket_drops_clients_that_stop_reading() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("status.sock");
+        let socket = StatusSocket::bind(&socket_path).unwrap();
+        let _stalled = UnixStream::connect(&socket_path).unwrap();
+
+        // Far more than a socket buffer holds; without the write timeout
+        // this blocks forever.
+        let path = "x".repeat(4096);
+        let started = std::time::Instant::now();
+        for _ in 0..1000 {
+            socket.on_file(Path::new(&path), 1);
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+
+        let reader = UnixStream::connect(&socket_path).unwrap();
+        socket.on_commit("after the stall");
+        drop(socket);
+        let events: Vec<Value> = BufReader::new(reader)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [json!({"event": "commit", "subject": "after the stall"})]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result, bail};
+use log::warn;
+
+/// Records the uid/gid of every synced path so a pull can restore ownership
+/// that git itself does not store.
+pub(crate) const OWNERSHIP_MANIFEST: &str = ".file-syncer-ownership";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Owner {
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+}
+
+#[cfg(unix)]
+pub(crate) fn owner_of(metadata: &fs::Metadata) -> Option<Owner> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(Owner {
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+    })
+}
+
+#[cfg(not(unix))]
+pub(crate) fn owner_of(_metadata: &fs::Metadata) -> Option<Owner> {
+    None
+}
+
+/// Applies `owner` to `path`. Lacking privileges is expected when not running
+/// as root, so that only logs a single warning per sync instead of failing.
+pub(crate) fn restore_owner(path: &Path, owner: Owner, warned: &AtomicBool) -> Result<()> {
+    match chown(path, owner) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            if !warned.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "Cannot restore file ownership without sufficient privileges; keeping current owners ({})",
+                    err
+                );
+            }
+            Ok(())
+        }
+        Err(err) => {
+            Err(err).with_context(|| format!("failed to restore ownership of {}", path.display()))
+        }
+    }
+}
+
+#[cfg(unix)]
+fn chown(path: &Path, owner: Owner) -> io::Result<()> {
+    std::os::unix::fs::lchown(path, Some(owner.uid), Some(owner.gid))
+}
+
+#[cfg(not(unix))]
+fn chown(_path: &Path, _owner: Owner) -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes `uid gid<TAB>path` lines sorted by path, so unchanged trees produce
+/// an unchanged manifest.
+pub(crate) fn write_manifest(root: &Path, entries: &[(PathBuf, Owner)]) -> Result<()> {
+    let mut entries = entries.to_vec();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut contents = String::new();
+    for (path, owner) in &entries {
+        let path = path.to_string_lossy();
+        if path.contains(['\n', '\t']) {
+            bail!("cannot record ownership of path with tab or newline: {path:?}");
+        }
+        contents.push_str(&format!("{} {}\t{}\n", owner.uid, owner.gid, path));
+    }
+
+    let manifest = root.join(OWNERSHIP_MANIFEST);
+    fs::write(&manifest, contents)
+        .with_context(|| format!("failed to write {}", manifest.display()))
+}
+
+/// Reads the manifest under `root`; a missing manifest yields no entries.
+pub(crate) fn read_manifest(root: &Path) -> Result<Vec<(PathBuf, Owner)>> {
+    let manifest = root.join(OWNERSHIP_MANIFEST);
+    let contents = match fs::read_to_string(&manifest) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read {}", manifest.display()));
+        }
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let parse = || {
+                let (ids, path) = line.split_once('\t')?;
+                let (uid, gid) = ids.split_once(' ')?;
+                let owner = Owner {
+                    uid: uid.parse().ok()?,
+                    gid: gid.parse().ok()?,
+                };
+                Some((PathBuf::from(path), owner))
+            };
+            let (path, owner) =
+                parse().with_context(|| format!("malformed ownership entry: {line:?}"))?;
+            crate::validate_repo_path(&path, "ownership manifest path")?;
+            Ok((path, owner))
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_round_trips_sorted_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let entries = vec![
+            (
+                PathBuf::from("b/file.txt"),
+                Owner {
+                    uid: 1000,
+                    gid: 100,
+                },
+            ),
+            (PathBuf::from("a"), Owner { uid: 0, gid: 0 }),
+        ];
+        write_manifest(temp_dir.path(), &entries).unwrap();
+
+        let contents = fs::read_to_string(temp_dir.path().join(OWNERSHIP_MANIFEST)).unwrap();
+        assert_eq!(contents, "0 0\ta\n1000 100\tb/file.txt\n");
+
+        let read = read_manifest(temp_dir.path()).unwrap();
+        assert_eq!(read, vec![entries[1].clone(), entries[0].clone()]);
+    }
+
+    #[test]
+    fn read_manifest_handles_missing_and_malformed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert!(read_manifest(temp_dir.path()).unwrap().is_empty());
+
+        fs::write(temp_dir.path().join(OWNERSHIP_MANIFEST), "root\tfile\n").unwrap();
+        assert!(read_manifest(temp_dir.path()).is_err());
+    }
+
+    #[test]
+    fn read_manifest_rejects_paths_outside_the_root() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        for path in ["../etc/passwd", "a/../../b", "/etc/shadow", ".git/config"] {
+            fs::write(
+                temp_dir.path().join(OWNERSHIP_MANIFEST),
+                format!("0 0\t{path}\n"),
+            )
+            .unwrap();
+            assert!(read_manifest(temp_dir.path()).is_err(), "{path}");
+        }
+    }
+
+    #[test]
+    fn owner_of_reports_file_owner() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("file.txt");
+        fs::write(&path, "content").unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(
+            owner_of(&metadata),
+            Some(Owner {
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+            })
+        );
+    }
+}
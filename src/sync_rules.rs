@@ -0,0 +1,432 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+pub(crate) const SYNC_INCLUDE_FILE: &str = ".syncinclude";
+pub(crate) const SYNC_IGNORE_FILE: &str = ".syncignore";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    AnyDepth,
+    Glob(Vec<char>),
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    segments: Vec<Segment>,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Pattern {
+    fn matches(&self, components: &[Cow<'_, str>], is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && match_segments(&self.segments, components)
+    }
+
+    fn may_match_below(&self, dir: &[Cow<'_, str>]) -> bool {
+        for (idx, component) in dir.iter().enumerate() {
+            match self.segments.get(idx) {
+                Some(Segment::AnyDepth) => return true,
+                Some(Segment::Glob(glob)) if glob_matches(glob, component) => {}
+                Some(Segment::Glob(_)) => return false,
+                // The pattern names an ancestor of `dir`.
+                None => return true,
+            }
+        }
+        self.segments.len() > dir.len()
+    }
+}
+
+/// Patterns from one rules file, evaluated like gitignore: the last pattern
+/// matching a path decides, and a path nothing matches inherits the decision
+/// for its closest matched ancestor.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PatternList {
+    patterns: Vec<Pattern>,
+}
+
+impl PatternList {
+    pub(crate) fn parse(contents: &str) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for (idx, line) in contents.lines().enumerate() {
+            if let Some(pattern) = parse_pattern(line)
+                .with_context(|| format!("invalid pattern on line {}", idx + 1))?
+            {
+                patterns.push(pattern);
+            }
+        }
+        Ok(Self { patterns })
+    }
+
+    pub(crate) fn single(pattern: &str) -> Result<Self> {
+        if pattern.starts_with(['#', '!']) || pattern.contains('\n') {
+            bail!("invalid pattern {pattern:?}");
+        }
+        let list = Self::parse(pattern)?;
+        if list.patterns.is_empty() {
+            bail!("empty pattern");
+        }
+        Ok(list)
+    }
+
+    fn decide(&self, components: &[Cow<'_, str>], is_dir: bool) -> Option<bool> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(components, is_dir))
+            .map(|pattern| !pattern.negated)
+    }
+
+    pub(crate) fn matches(&self, rel: &Path, is_dir: bool) -> bool {
+        let components = path_components(rel);
+        (1..=components.len())
+            .rev()
+            .find_map(|len| self.decide(&components[..len], is_dir || len < components.len()))
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn may_match_below(&self, dir: &Path) -> bool {
+        let components = path_components(dir);
+        self.patterns
+            .iter()
+            .any(|pattern| !pattern.negated && pattern.may_match_below(&components))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SyncRules {
+    include: Option<PatternList>,
+    ignore: Option<PatternList>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Sync,
+    Prune,
+    /// Only create the directory if something inside it is synced.
+    Descend,
+}
+
+impl SyncRules {
+    pub(crate) fn load(root: &Path) -> Result<Self> {
+        Ok(Self {
+            include: read_pattern_file(&root.join(SYNC_INCLUDE_FILE))?,
+            ignore: read_pattern_file(&root.join(SYNC_IGNORE_FILE))?,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.include.is_none() && self.ignore.is_none()
+    }
+
+    pub(crate) fn verdict(&self, rel: &Path, is_dir: bool) -> Verdict {
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|include| include.matches(rel, is_dir));
+        let verdict = if included {
+            Verdict::Sync
+        } else if is_dir
+            && self
+                .include
+                .as_ref()
+                .is_some_and(|list| list.may_match_below(rel))
+        {
+            Verdict::Descend
+        } else {
+            Verdict::Prune
+        };
+        match &self.ignore {
+            Some(ignore) if verdict != Verdict::Prune && ignore.matches(rel, is_dir) => {
+                Verdict::Prune
+            }
+            _ => verdict,
+        }
+    }
+}
+
+fn read_pattern_file(path: &Path) -> Result<Option<PatternList>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => PatternList::parse(&contents)
+            .map(Some)
+            .with_context(|| format!("failed to parse {}", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Invalid UTF-8 becomes U+FFFD, so such a component keeps its place and
+/// only wildcards match the replaced bytes.
+fn path_components(path: &Path) -> Vec<Cow<'_, str>> {
+    path.iter().map(|part| part.to_string_lossy()).collect()
+}
+
+fn parse_pattern(line: &str) -> Result<Option<Pattern>> {
+    let line = line.trim_end_matches([' ', '\t', '\r']);
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let (negated, rest) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    let (dir_only, rest) = match rest.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    // A slash anywhere but the end anchors the pattern to the root;
+    // otherwise it matches a name at any depth.
+    let anchored = rest.contains('/');
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    if rest.is_empty() {
+        bail!("empty pattern {line:?}");
+    }
+
+    let mut segments = Vec::new();
+    if !anchored {
+        segments.push(Segment::AnyDepth);
+    }
+    for part in rest.split('/') {
+        if part.is_empty() {
+            bail!("empty path component in {line:?}");
+        }
+        let segment = if part == "**" {
+            Segment::AnyDepth
+        } else {
+            let glob: Vec<char> = part.chars().collect();
+            validate_glob(&glob).with_context(|| format!("in pattern {line:?}"))?;
+            Segment::Glob(glob)
+        };
+        if segment == Segment::AnyDepth && segments.last() == Some(&Segment::AnyDepth) {
+            continue;
+        }
+        segments.push(segment);
+    }
+
+    Ok(Some(Pattern {
+        segments,
+        negated,
+        dir_only,
+    }))
+}
+
+fn validate_glob(glob: &[char]) -> Result<()> {
+    let mut idx = 0;
+    while idx < glob.len() {
+        match glob[idx] {
+            '\\' => idx += 2,
+            '[' => match class_end(glob, idx) {
+                Some(end) => idx = end + 1,
+                None => bail!("unterminated character class"),
+            },
+            _ => idx += 1,
+        }
+    }
+    Ok(())
+}
+
+fn match_segments(segments: &[Segment], components: &[Cow<'_, str>]) -> bool {
+    match segments.split_first() {
+        None => components.is_empty(),
+        Some((Segment::AnyDepth, rest)) => {
+            (0..=components.len()).any(|skip| match_segments(rest, &components[skip..]))
+        }
+        Some((Segment::Glob(glob), rest)) => components
+            .split_first()
+            .is_some_and(|(first, tail)| glob_matches(glob, first) && match_segments(rest, tail)),
+    }
+}
+
+fn glob_matches(glob: &[char], name: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let (mut g, mut n) = (0, 0);
+    // Position after the last `*` and the name position it resumed from.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        let step = match glob.get(g) {
+            Some('*') => {
+                backtrack = Some((g + 1, n));
+                g += 1;
+                continue;
+            }
+            Some('?') => Some(g + 1),
+            Some('[') => match class_end(glob, g) {
+                Some(end) => class_matches(&glob[g + 1..end], name[n]).then_some(end + 1),
+                None => (name[n] == '[').then_some(g + 1),
+            },
+            Some('\\') if g + 1 < glob.len() => (glob[g + 1] == name[n]).then_some(g + 2),
+            Some(&literal) => (literal == name[n]).then_some(g + 1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(next), _) => {
+                g = next;
+                n += 1;
+            }
+            (None, Some((star_next, star_n))) => {
+                g = star_next;
+                n = star_n + 1;
+                backtrack = Some((star_next, star_n + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+fn class_end(glob: &[char], start: usize) -> Option<usize> {
+    let mut idx = start + 1;
+    if matches!(glob.get(idx), Some('!' | '^')) {
+        idx += 1;
+    }
+    // A `]` right after the opening bracket is a literal.
+    if glob.get(idx) == Some(&']') {
+        idx += 1;
+    }
+    while idx < glob.len() {
+        match glob[idx] {
+            '\\' => idx += 2,
+            ']' => return Some(idx),
+            _ => idx += 1,
+        }
+    }
+    None
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut idx = 0;
+    while idx < class.len() {
+        let mut low = class[idx];
+        if low == '\\' && idx + 1 < class.len() {
+            idx += 1;
+            low = class[idx];
+        }
+        if class.get(idx + 1) == Some(&'-') && idx + 2 < class.len() {
+            if (low..=class[idx + 2]).contains(&c) {
+                found = true;
+            }
+            idx += 3;
+        } else {
+            if low == c {
+                found = true;
+            }
+            idx += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(contents: &str) -> PatternList {
+        PatternList::parse(contents).unwrap()
+    }
+
+    #[test]
+    fn glob_matches_wildcards_and_classes() {
+        let glob = |pattern: &str| pattern.chars().collect::<Vec<_>>();
+        assert!(glob_matches(&glob("*.rs"), "main.rs"));
+        assert!(!glob_matches(&glob("*.rs"), "main.rsx"));
+        assert!(glob_matches(&glob("a*b*c"), "axxbyybc"));
+        assert!(glob_matches(&glob("file?.txt"), "file1.txt"));
+        assert!(glob_matches(&glob("[a-c]x"), "bx"));
+        assert!(!glob_matches(&glob("[!a-c]x"), "bx"));
+        assert!(glob_matches(&glob("\\*"), "*"));
+        assert!(!glob_matches(&glob("\\*"), "a"));
+        assert!(PatternList::parse("[abc\n").is_err());
+    }
+
+    #[test]
+    fn pattern_list_follows_gitignore_rules() {
+        let rules = list("# build output\n*.log\n!keep.log\n/target/\ndocs/**/*.md\n");
+        assert!(rules.matches(Path::new("a/b/debug.log"), false));
+        assert!(!rules.matches(Path::new("a/keep.log"), false));
+        assert!(rules.matches(Path::new("target/out.bin"), false));
+        assert!(!rules.matches(Path::new("src/target/out.bin"), false));
+        // Directory-only patterns do not match a file of that name.
+        assert!(!rules.matches(Path::new("target"), false));
+        assert!(rules.matches(Path::new("docs/guide.md"), false));
+        assert!(rules.matches(Path::new("docs/a/b/guide.md"), false));
+        assert!(!rules.matches(Path::new("guide.md"), false));
+    }
+
+    #[test]
+    fn allowlist_descends_only_where_matches_are_possible() {
+        let rules = SyncRules {
+            include: Some(list("src/**/*.rs\nREADME.md\nassets/\n")),
+            ignore: None,
+        };
+        assert_eq!(rules.verdict(Path::new("src"), true), Verdict::Descend);
+        assert_eq!(
+            rules.verdict(Path::new("src/a/lib.rs"), false),
+            Verdict::Sync
+        );
+        assert_eq!(
+            rules.verdict(Path::new("src/a/lib.c"), false),
+            Verdict::Prune
+        );
+        assert_eq!(rules.verdict(Path::new("assets"), true), Verdict::Sync);
+        assert_eq!(
+            rules.verdict(Path::new("assets/x/y.png"), false),
+            Verdict::Sync
+        );
+        // `README.md` is unanchored, so any directory may hold one.
+        assert_eq!(rules.verdict(Path::new("other"), true), Verdict::Descend);
+
+        let anchored = SyncRules {
+            include: Some(list("/src/*.rs\n")),
+            ignore: None,
+        };
+        assert_eq!(anchored.verdict(Path::new("other"), true), Verdict::Prune);
+        assert_eq!(
+            anchored.verdict(Path::new("src/nested"), true),
+            Verdict::Prune
+        );
+    }
+
+    #[test]
+    fn ignore_list_applies_after_allowlist() {
+        let rules = SyncRules {
+            include: Some(list("config/\n")),
+            ignore: Some(list("*.secret\n")),
+        };
+        assert_eq!(
+            rules.verdict(Path::new("config/app.toml"), false),
+            Verdict::Sync
+        );
+        assert_eq!(
+            rules.verdict(Path::new("config/db.secret"), false),
+            Verdict::Prune
+        );
+        assert_eq!(rules.verdict(Path::new("notes.txt"), false), Verdict::Prune);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_components_keep_their_place() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = |bytes: &[u8]| Path::new(OsStr::from_bytes(bytes)).to_path_buf();
+        let rules = list("/src/*.rs\n*.log\n");
+        // The invalid directory must not be dropped, which would turn
+        // `src/\xff/main.rs` into the anchored `src/main.rs`.
+        assert!(!rules.matches(&path(b"src/\xff/main.rs"), false));
+        assert!(rules.matches(&path(b"src/\xff.rs"), false));
+        assert!(rules.matches(&path(b"\xff/app.log"), false));
+        assert!(rules.matches(&path(b"logs/\xfe\xff.log"), false));
+        assert!(!rules.matches(&path(b"\xff"), false));
+    }
+}
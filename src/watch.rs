@@ -0,0 +1,298 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::interrupt::check_interrupted;
+
+pub(crate) const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(2);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An empty `rel_path` means events were lost and the whole tree may have
+/// changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Change {
+    pub(crate) rel_path: PathBuf,
+    pub(crate) is_dir: bool,
+}
+
+/// Deletions are not reported, as a push never removes files from the
+/// repository.
+pub(crate) struct Watcher {
+    root: PathBuf,
+    inotify: sys::Inotify,
+    dirs: HashMap<i32, PathBuf>,
+}
+
+impl Watcher {
+    pub(crate) fn new(root: &Path) -> Result<Self> {
+        let inotify = sys::Inotify::new().context("failed to start watching for changes")?;
+        let mut watcher = Self {
+            root: root.to_path_buf(),
+            inotify,
+            dirs: HashMap::new(),
+        };
+        watcher.watch_tree(Path::new(""))?;
+        Ok(watcher)
+    }
+
+    fn watch_tree(&mut self, rel_dir: &Path) -> Result<()> {
+        for entry in WalkDir::new(self.root.join(rel_dir)) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                // Removed again before the watch could be added.
+                Err(err)
+                    if err
+                        .io_error()
+                        .is_some_and(|io| io.kind() == std::io::ErrorKind::NotFound) =>
+                {
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            let rel_path = entry
+                .path()
+                .strip_prefix(&self.root)
+                .context("failed to compute relative path")?
+                .to_path_buf();
+            match self.inotify.add_watch(entry.path()) {
+                Ok(wd) => {
+                    self.dirs.insert(wd, rel_path);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("failed to watch {}", entry.path().display()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn poll(&mut self, timeout: Duration) -> Result<Vec<Change>> {
+        let mut changes = Vec::new();
+        if !self.inotify.wait(timeout)? {
+            return Ok(changes);
+        }
+        for event in self.inotify.read_events()? {
+            if event.overflowed {
+                warn!("Change notifications overflowed; treating the whole folder as changed");
+                changes.push(Change {
+                    rel_path: PathBuf::new(),
+                    is_dir: true,
+                });
+                continue;
+            }
+            if event.watch_removed {
+                self.dirs.remove(&event.wd);
+                continue;
+            }
+            let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) else {
+                continue;
+            };
+            let rel_path = dir.join(name);
+            if event.is_dir && event.appeared {
+                self.watch_tree(&rel_path)?;
+            }
+            changes.push(Change {
+                rel_path,
+                is_dir: event.is_dir,
+            });
+        }
+        Ok(changes)
+    }
+}
+
+pub(crate) fn wait_for_changes(
+    watcher: &mut Watcher,
+    debounce: Duration,
+    mut relevant: impl FnMut(&Change) -> bool,
+) -> Result<BTreeSet<PathBuf>> {
+    let mut changed = BTreeSet::new();
+    let mut quiet_until: Option<Instant> = None;
+    loop {
+        check_interrupted()?;
+        let timeout = match quiet_until {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => remaining.min(POLL_INTERVAL),
+                _ => return Ok(changed),
+            },
+            None => POLL_INTERVAL,
+        };
+        for change in watcher.poll(timeout)? {
+            if relevant(&change) {
+                debug!("Changed: {}", change.rel_path.display());
+                changed.insert(change.rel_path);
+                quiet_until = Some(Instant::now() + debounce);
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct Event {
+    wd: i32,
+    name: Option<PathBuf>,
+    is_dir: bool,
+    appeared: bool,
+    watch_removed: bool,
+    overflowed: bool,
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    const WATCH_MASK: u32 = libc::IN_CREATE
+        | libc::IN_MODIFY
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_TO
+        | libc::IN_ATTRIB
+        | libc::IN_ONLYDIR
+        | libc::IN_DONT_FOLLOW;
+
+    use super::Event;
+
+    pub(super) struct Inotify {
+        fd: OwnedFd,
+    }
+
+    impl Inotify {
+        pub(super) fn new() -> io::Result<Self> {
+            // Safety: no pointers are passed.
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Safety: `fd` was just returned by the kernel and is owned by nobody else.
+            Ok(Self {
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            })
+        }
+
+        pub(super) fn add_watch(&self, path: &Path) -> io::Result<i32> {
+            let path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            // Safety: `path` is NUL-terminated.
+            let wd =
+                unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), WATCH_MASK) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(wd)
+        }
+
+        // A signal counts as no events, so the caller gets to check for an
+        // interrupt.
+        pub(super) fn wait(&self, timeout: Duration) -> io::Result<bool> {
+            let mut poll_fd = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+            // Safety: `poll_fd` is a single valid entry.
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout) };
+            if ready < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    return Ok(false);
+                }
+                return Err(err);
+            }
+            Ok(ready > 0)
+        }
+
+        pub(super) fn read_events(&self) -> io::Result<Vec<Event>> {
+            let mut events = Vec::new();
+            let mut buffer = vec![0u8; 64 * 1024];
+            loop {
+                // Safety: `buffer` is valid for its length.
+                let read = unsafe {
+                    libc::read(
+                        self.fd.as_raw_fd(),
+                        buffer.as_mut_ptr().cast(),
+                        buffer.len(),
+                    )
+                };
+                if read < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::WouldBlock {
+                        return Ok(events);
+                    }
+                    return Err(err);
+                }
+                parse_events(&buffer[..read as usize], &mut events);
+            }
+        }
+    }
+
+    fn parse_events(mut bytes: &[u8], events: &mut Vec<Event>) {
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+        while bytes.len() >= HEADER {
+            // Safety: at least a header's worth of bytes remain; the read is
+            // unaligned because the buffer carries no alignment guarantee.
+            let header: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast()) };
+            let end = (HEADER + header.len as usize).min(bytes.len());
+            let name = &bytes[HEADER..end];
+            let name = &name[..name
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(name.len())];
+            events.push(Event {
+                wd: header.wd,
+                name: (!name.is_empty()).then(|| PathBuf::from(OsStr::from_bytes(name))),
+                is_dir: header.mask & libc::IN_ISDIR != 0,
+                appeared: header.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0,
+                watch_removed: header.mask & libc::IN_IGNORED != 0,
+                overflowed: header.mask & libc::IN_Q_OVERFLOW != 0,
+            });
+            bytes = &bytes[end..];
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::io;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::Event;
+
+    pub(super) enum Inotify {}
+
+    impl Inotify {
+        pub(super) fn new() -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "watching for changes is only supported on Linux",
+            ))
+        }
+
+        pub(super) fn add_watch(&self, _path: &Path) -> io::Result<i32> {
+            match *self {}
+        }
+
+        pub(super) fn wait(&self, _timeout: Duration) -> io::Result<bool> {
+            match *self {}
+        }
+
+        pub(super) fn read_events(&self) -> io::Result<Vec<Event>> {
+            match *self {}
+        }
+    }
+}
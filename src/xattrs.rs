@@ -0,0 +1,350 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result, bail};
+use log::{debug, warn};
+
+pub(crate) const XATTR_MANIFEST: &str = ".file-syncer-xattrs";
+
+pub(crate) type Xattrs = Vec<(String, Vec<u8>)>;
+
+/// `security.*` and `trusted.*` grant privileges, so a repository must not
+/// be able to set them unless explicitly allowed.
+const USER_NAMESPACE: &str = "user.";
+
+fn is_synced(name: &str, all_namespaces: bool) -> bool {
+    all_namespaces || name.starts_with(USER_NAMESPACE)
+}
+
+/// Does not follow symlinks.
+pub(crate) fn read_xattrs(path: &Path, all_namespaces: bool) -> Result<Xattrs> {
+    let mut attrs = Vec::new();
+    let names = match sys::list(path) {
+        Ok(names) => names,
+        Err(err) if is_unsupported(&err) => return Ok(attrs),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("failed to list xattrs of {}", path.display()));
+        }
+    };
+    for name in names {
+        let Ok(name) = String::from_utf8(name) else {
+            warn!("Skipping non-UTF-8 xattr name on {}", path.display());
+            continue;
+        };
+        if !is_synced(&name, all_namespaces) {
+            continue;
+        }
+        match sys::get(path, &name) {
+            Ok(value) => attrs.push((name, value)),
+            // Removed between listing and reading.
+            Err(err) if err.raw_os_error() == Some(sys::ENODATA) => {}
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read xattr {name} of {}", path.display()));
+            }
+        }
+    }
+    attrs.sort();
+    Ok(attrs)
+}
+
+/// Missing xattr support and privileged namespaces are expected, so they
+/// only log a single warning per sync instead of failing.
+pub(crate) fn restore_xattrs(
+    path: &Path,
+    attrs: &Xattrs,
+    all_namespaces: bool,
+    warned: &AtomicBool,
+) -> Result<()> {
+    for (name, value) in attrs {
+        if !is_synced(name, all_namespaces) {
+            debug!("Not restoring xattr {name} on {}", path.display());
+            continue;
+        }
+        match sys::set(path, name, value) {
+            Ok(()) => {}
+            Err(err) if is_unsupported(&err) || err.kind() == io::ErrorKind::PermissionDenied => {
+                if !warned.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Cannot restore extended attribute {name} on {}; skipping attributes that cannot be set ({})",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("failed to restore xattr {name} of {}", path.display())
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_unsupported(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(sys::ENOTSUP)
+}
+
+/// Sorted, so unchanged trees produce an unchanged manifest.
+pub(crate) fn write_xattr_manifest(root: &Path, entries: &[(PathBuf, Xattrs)]) -> Result<()> {
+    let mut entries = entries.to_vec();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut contents = String::new();
+    for (path, attrs) in &entries {
+        let path = path.to_string_lossy();
+        if path.contains(['\n', '\t']) {
+            bail!("cannot record xattrs of path with tab or newline: {path:?}");
+        }
+        for (name, value) in attrs {
+            if name.contains([' ', '\n', '\t']) {
+                bail!("cannot record xattr name with whitespace: {name:?}");
+            }
+            let hex: String = value.iter().map(|byte| format!("{byte:02x}")).collect();
+            contents.push_str(&format!("{name} {hex}\t{path}\n"));
+        }
+    }
+
+    let manifest = root.join(XATTR_MANIFEST);
+    fs::write(&manifest, contents)
+        .with_context(|| format!("failed to write {}", manifest.display()))
+}
+
+pub(crate) fn read_xattr_manifest(root: &Path) -> Result<Vec<(PathBuf, Xattrs)>> {
+    let manifest = root.join(XATTR_MANIFEST);
+    let contents = match fs::read_to_string(&manifest) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read {}", manifest.display()));
+        }
+    };
+
+    let mut entries: Vec<(PathBuf, Xattrs)> = Vec::new();
+    for line in contents.lines().filter(|line| !line.is_empty()) {
+        let parse = || {
+            let (attr, path) = line.split_once('\t')?;
+            let (name, hex) = attr.split_once(' ')?;
+            if hex.len() % 2 != 0 {
+                return None;
+            }
+            let value = (0..hex.len())
+                .step_by(2)
+                .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            Some((PathBuf::from(path), name.to_string(), value))
+        };
+        let (path, name, value) =
+            parse().with_context(|| format!("malformed xattr entry: {line:?}"))?;
+        crate::validate_repo_path(&path, "xattr manifest path")?;
+        match entries.last_mut() {
+            Some((last, attrs)) if *last == path => attrs.push((name, value)),
+            _ => entries.push((path, vec![(name, value)])),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    pub(super) const ENOTSUP: i32 = libc::ENOTSUP;
+    pub(super) const ENODATA: i32 = libc::ENODATA;
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+
+    // Another process may change the attributes between sizing and reading.
+    fn read_sized(mut fill: impl FnMut(&mut [u8]) -> isize) -> io::Result<Vec<u8>> {
+        loop {
+            let size = fill(&mut []);
+            if size < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut buffer = vec![0u8; size as usize];
+            let read = fill(&mut buffer);
+            if read < 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::ERANGE) {
+                    continue;
+                }
+                return Err(err);
+            }
+            buffer.truncate(read as usize);
+            return Ok(buffer);
+        }
+    }
+
+    pub(super) fn list(path: &Path) -> io::Result<Vec<Vec<u8>>> {
+        let path = c_path(path)?;
+        let names = read_sized(|buffer| {
+            // Safety: `path` is NUL-terminated and `buffer` is valid for its length.
+            unsafe { libc::llistxattr(path.as_ptr(), buffer.as_mut_ptr().cast(), buffer.len()) }
+        })?;
+        Ok(names
+            .split(|&byte| byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect())
+    }
+
+    pub(super) fn get(path: &Path, name: &str) -> io::Result<Vec<u8>> {
+        let path = c_path(path)?;
+        let name =
+            CString::new(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        read_sized(|buffer| {
+            // Safety: both strings are NUL-terminated and `buffer` is valid for its length.
+            unsafe {
+                libc::lgetxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            }
+        })
+    }
+
+    pub(super) fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        let path = c_path(path)?;
+        let name =
+            CString::new(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        // Safety: both strings are NUL-terminated and `value` is valid for its length.
+        let result = unsafe {
+            libc::lsetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::io;
+    use std::path::Path;
+
+    pub(super) const ENOTSUP: i32 = -1;
+    pub(super) const ENODATA: i32 = -2;
+
+    fn unsupported() -> io::Error {
+        io::Error::from_raw_os_error(ENOTSUP)
+    }
+
+    pub(super) fn list(_path: &Path) -> io::Result<Vec<Vec<u8>>> {
+        Err(unsupported())
+    }
+
+    pub(super) fn get(_path: &Path, _name: &str) -> io::Result<Vec<u8>> {
+        Err(unsupported())
+    }
+
+    pub(super) fn set(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xattr_manifest_round_trips_grouped_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let entries = vec![
+            (
+                PathBuf::from("b/file.txt"),
+                vec![
+                    ("user.a".to_string(), b"1".to_vec()),
+                    ("user.b".to_string(), vec![0, 0xff]),
+                ],
+            ),
+            (
+                PathBuf::from("a"),
+                vec![("user.empty".to_string(), Vec::new())],
+            ),
+        ];
+        write_xattr_manifest(temp_dir.path(), &entries).unwrap();
+
+        let contents = fs::read_to_string(temp_dir.path().join(XATTR_MANIFEST)).unwrap();
+        assert_eq!(
+            contents,
+            "user.empty \ta\nuser.a 31\tb/file.txt\nuser.b 00ff\tb/file.txt\n"
+        );
+
+        let read = read_xattr_manifest(temp_dir.path()).unwrap();
+        assert_eq!(read, vec![entries[1].clone(), entries[0].clone()]);
+
+        fs::write(temp_dir.path().join(XATTR_MANIFEST), "user.a zz\tfile\n").unwrap();
+        assert!(read_xattr_manifest(temp_dir.path()).is_err());
+    }
+
+    #[test]
+    fn read_xattr_manifest_rejects_paths_outside_the_root() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        for path in ["../etc/passwd", "a/../../b", "/usr/bin/ping", ".git/hooks"] {
+            fs::write(
+                temp_dir.path().join(XATTR_MANIFEST),
+                format!("user.a 31\t{path}\n"),
+            )
+            .unwrap();
+            assert!(read_xattr_manifest(temp_dir.path()).is_err(), "{path}");
+        }
+    }
+
+    #[test]
+    fn read_and_restore_xattrs_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let src = temp_dir.path().join("src.txt");
+        let dst = temp_dir.path().join("dst.txt");
+        fs::write(&src, "content").unwrap();
+        fs::write(&dst, "content").unwrap();
+
+        match sys::set(&src, "user.file-syncer", b"value") {
+            Ok(()) => {}
+            Err(err) if is_unsupported(&err) => return,
+            Err(err) => panic!("failed to set xattr: {err}"),
+        }
+
+        let attrs = read_xattrs(&src, false).unwrap();
+        assert_eq!(
+            attrs,
+            vec![("user.file-syncer".to_string(), b"value".to_vec())]
+        );
+        restore_xattrs(&dst, &attrs, false, &AtomicBool::new(false)).unwrap();
+        assert_eq!(read_xattrs(&dst, false).unwrap(), attrs);
+    }
+
+    #[test]
+    fn restore_xattrs_skips_privileged_namespaces_by_default() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("file.txt");
+        fs::write(&path, "content").unwrap();
+
+        // Setting these would fail unprivileged, so skipping is the only way
+        // this succeeds without touching the file.
+        let attrs = vec![
+            ("security.capability".to_string(), vec![1, 2, 3]),
+            ("trusted.file-syncer".to_string(), b"value".to_vec()),
+        ];
+        restore_xattrs(&path, &attrs, false, &AtomicBool::new(false)).unwrap();
+        assert!(read_xattrs(&path, true).unwrap().is_empty());
+    }
+}